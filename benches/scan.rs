@@ -0,0 +1,103 @@
+//!
+//! Scan benchmark
+//!
+//! Times dutree over a generated tree of empty files. If strace is installed, also
+//! counts the stat and path lookup system calls made for each entry
+//!
+//! `cargo bench --bench scan`
+//!
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const DIRS  : usize = 100;
+const FILES : usize = 300;
+const RUNS  : u32   = 10;
+
+// syscalls that look up a path or an open file
+const TRACED : &str = "trace=stat,lstat,fstat,newfstatat,statx,getcwd,readlink,readlinkat,openat";
+
+fn make_tree() -> PathBuf {
+    let root = env::temp_dir().join( format!( "dutree-bench-{}", std::process::id() ) );
+    let _ = fs::remove_dir_all( &root );
+    for d in 0..DIRS {
+        let dir = root.join( format!( "dir{}/sub", d ) );
+        fs::create_dir_all( &dir ).unwrap();
+        for f in 0..FILES {
+            fs::File::create( dir.join( format!( "file{}", f ) ) ).unwrap();
+        }
+    }
+    root
+}
+
+fn dutree( root : &Path ) -> Command {
+    let mut cmd = Command::new( env!( "CARGO_BIN_EXE_dutree" ) );
+    cmd.args( [ "-A", "-j1" ] ).arg( root ).stdout( Stdio::null() ).stderr( Stdio::null() );
+    cmd
+}
+
+fn time_runs( root : &Path ) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        assert!( dutree( root ).status().unwrap().success() );
+    }
+    start.elapsed() / RUNS
+}
+
+// per syscall counts from strace -c, None if strace can't be run
+fn count_syscalls( root : &Path ) -> Option<Vec<(String, u64)>> {
+    let out = root.with_extension( "strace" );
+    let cmd = dutree( root );
+    let status = Command::new( "strace" )
+        .args( [ "-f", "-c", "-e", TRACED, "-o" ] ).arg( &out )
+        .arg( cmd.get_program() ).args( cmd.get_args() )
+        .stdout( Stdio::null() ).stderr( Stdio::null() )
+        .status().ok()?;
+    if !status.success() { return None }
+
+    let report = fs::read_to_string( &out ).ok()?;
+    let _ = fs::remove_file( &out );
+
+    // % time, seconds, usecs/call, calls, [errors,] syscall
+    Some( report.lines()
+        .map( |line| line.split_whitespace().collect::<Vec<&str>>() )
+        .filter( |cols| cols.len() >= 5 && cols[0].parse::<f64>().is_ok() && cols[cols.len()-1] != "total" )
+        .map( |cols| ( cols[cols.len()-1].to_string(), cols[3].parse().unwrap_or( 0 ) ) )
+        .collect() )
+}
+
+fn main() {
+    let root    = make_tree();
+    let entries = ( DIRS * ( FILES + 2 ) + 1 ) as u64;
+
+    println!( "scan of {} entries: {:?} per run", entries, time_runs( &root ) );
+    match count_syscalls( &root ) {
+        Some( counts ) => {
+            for ( syscall, calls ) in counts {
+                println!( "{:>12} {:>8} calls {:>6.2} per entry", syscall, calls, calls as f64 / entries as f64 );
+            }
+        },
+        None => println!( "install strace to count system calls" ),
+    }
+
+    fs::remove_dir_all( &root ).unwrap();
+}
+
+// License
+//
+// This script is free software; you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This script is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this script; if not, write to the
+// Free Software Foundation, Inc., 59 Temple Place, Suite 330,
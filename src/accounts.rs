@@ -0,0 +1,66 @@
+//!
+//! User and group names
+//!
+//! Read straight from /etc/passwd and /etc/group, so no NSS module gets loaded and no
+//! network directory is queried. Accounts only known elsewhere can be given by id
+//!
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Default)]
+pub struct Names {
+    by_id   : HashMap<u32, String>,
+    by_name : HashMap<String, u32>,
+}
+
+impl Names {
+    pub fn users() -> Names {
+        Names::read( Path::new( "/etc/passwd" ) )
+    }
+
+    pub fn groups() -> Names {
+        Names::read( Path::new( "/etc/group" ) )
+    }
+
+    // name:password:id:... lines, both files start the same way. Missing files give no names
+    pub fn read( path : &Path ) -> Names {
+        let mut names = Names::default();
+        let text = fs::read_to_string( path ).unwrap_or_default();
+        for line in text.lines().filter( |line| !line.starts_with( '#' ) ) {
+            let fields : Vec<&str> = line.split( ':' ).collect();
+            if let ( Some( name ), Some( Ok( id ) ) ) = ( fields.first(), fields.get( 2 ).map( |id| id.parse() ) ) {
+                // the first entry wins for ids listed more than once, like getpwuid does
+                names.by_id.entry( id ).or_insert_with( || name.to_string() );
+                names.by_name.entry( name.to_string() ).or_insert( id );
+            }
+        }
+        names
+    }
+
+    // names are looked up first, like chown does, then numeric ids
+    pub fn id( &self, name : &str ) -> Option<u32> {
+        self.by_name.get( name ).copied().or_else( || name.parse().ok() )
+    }
+
+    pub fn name( &self, id : u32 ) -> String {
+        self.by_id.get( &id ).cloned().unwrap_or_else( || id.to_string() )
+    }
+}
+
+// License
+//
+// This script is free software; you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This script is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this script; if not, write to the
+// Free Software Foundation, Inc., 59 Temple Place, Suite 330,
@@ -0,0 +1,122 @@
+//!
+//! Filesystem access for the scanner
+//!
+//! The scanner only needs to stat entries, list directories, read links and the odd
+//! small file like .gitignore. The
+//! portable backend does it through `std::fs` and full paths. On Linux, entries are
+//! reached from their parent directory descriptor instead, see `backend::linux`
+//!
+
+use std::io;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+// only used by tests on Linux
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+pub mod portable;
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod uring;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+}
+
+impl FileType {
+    pub fn from_mode( mode : u32 ) -> Option<FileType> {
+        match mode & 0o170000 {
+            0o100000 => Some( FileType::File ),
+            0o040000 => Some( FileType::Dir ),
+            0o120000 => Some( FileType::Symlink ),
+            0o010000 => Some( FileType::Fifo ),
+            0o140000 => Some( FileType::Socket ),
+            0o060000 => Some( FileType::BlockDevice ),
+            0o020000 => Some( FileType::CharDevice ),
+            _        => None,
+        }
+    }
+}
+
+// the part of struct stat the scanner uses
+#[derive(Clone, Copy, Debug)]
+pub struct Stat {
+    pub dev    : u64,
+    pub ino    : u64,
+    pub mode   : u32,
+    pub nlink  : u64,
+    pub size   : u64,
+    pub blocks : u64,   // 512 byte units
+    pub mtime  : i64,   // seconds since the epoch
+    pub atime  : i64,
+    pub uid    : u32,
+    pub gid    : u32,
+}
+
+impl Stat {
+    pub fn file_type( &self ) -> Option<FileType> {
+        FileType::from_mode( self.mode )
+    }
+
+    pub fn is_dir( &self ) -> bool {
+        self.file_type() == Some( FileType::Dir )
+    }
+}
+
+pub struct DirEntry {
+    pub name      : OsString,
+    pub file_type : Option<FileType>,   // None if the directory listing doesn't say
+}
+
+// a scan root, or an entry of a directory opened by the same backend
+pub enum At<'a, D> {
+    Root( &'a Path ),
+    Entry( &'a D, &'a OsStr ),
+}
+
+impl<D> Clone for At<'_, D> {
+    fn clone( &self ) -> Self { *self }
+}
+impl<D> Copy for At<'_, D> {}
+
+pub trait Backend : Sync {
+    type Dir : Send + Sync;     // an open directory
+
+    // follow only matters for symlinks
+    fn stat( &self, at : At<Self::Dir>, follow : bool ) -> io::Result<Stat>;
+    fn open_dir( &self, at : At<Self::Dir>, follow : bool ) -> io::Result<Self::Dir>;
+    // entries other than . and .., in the order the filesystem gives them
+    fn read_dir( &self, dir : &Self::Dir ) -> io::Result<Vec<io::Result<DirEntry>>>;
+    fn read_link( &self, at : At<Self::Dir> ) -> io::Result<PathBuf>;
+    // up to limit bytes from the start of a file, links are followed
+    fn read_file( &self, at : At<Self::Dir>, limit : u64 ) -> io::Result<Vec<u8>>;
+
+    // stats the given entries of dir all at once, each one following links or not.
+    // None means the backend can't, and they are stat'ed one by one instead
+    fn stat_batch( &self, _dir : &Self::Dir, _entries : &[( &OsStr, bool )] ) -> Option<Vec<io::Result<Stat>>> {
+        None
+    }
+}
+
+// License
+//
+// This script is free software; you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This script is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this script; if not, write to the
+// Free Software Foundation, Inc., 59 Temple Place, Suite 330,
@@ -0,0 +1,142 @@
+//!
+//! Linux backend
+//!
+//! Directories are opened relative to their parent descriptor with openat(2), listed
+//! with getdents64(2) and their entries stat'ed with fstatat(2). The kernel never has
+//! to resolve a full path, so depth is not limited by PATH_MAX
+//!
+
+use super::{At, Backend, DirEntry, FileType, Stat};
+
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::PathBuf;
+
+const DENTS_BUF : usize = 32 * 1024;
+
+pub struct Linux;
+
+impl Linux {
+    // every directory being scanned holds a descriptor until its children are done,
+    // deep trees need more than the usual soft limit of open files
+    pub fn new() -> Linux {
+        unsafe {
+            let mut limit : libc::rlimit = mem::zeroed();
+            if libc::getrlimit( libc::RLIMIT_NOFILE, &mut limit ) == 0 && limit.rlim_cur < limit.rlim_max {
+                limit.rlim_cur = limit.rlim_max;
+                libc::setrlimit( libc::RLIMIT_NOFILE, &limit );
+            }
+        }
+        Linux
+    }
+}
+
+// scan roots are relative to the current directory
+fn at_args( at : At<OwnedFd> ) -> io::Result<( RawFd, CString )> {
+    Ok( match at {
+        At::Root( path )       => ( libc::AT_FDCWD, CString::new( path.as_os_str().as_bytes() )? ),
+        At::Entry( dir, name ) => ( dir.as_raw_fd(), CString::new( name.as_bytes() )? ),
+    } )
+}
+
+fn cvt( ret : libc::c_long ) -> io::Result<libc::c_long> {
+    if ret < 0 { Err( io::Error::last_os_error() ) } else { Ok( ret ) }
+}
+
+fn file_type_from_dtype( d_type : u8 ) -> Option<FileType> {
+    match d_type {
+        libc::DT_REG  => Some( FileType::File        ),
+        libc::DT_DIR  => Some( FileType::Dir         ),
+        libc::DT_LNK  => Some( FileType::Symlink     ),
+        libc::DT_FIFO => Some( FileType::Fifo        ),
+        libc::DT_SOCK => Some( FileType::Socket      ),
+        libc::DT_BLK  => Some( FileType::BlockDevice ),
+        libc::DT_CHR  => Some( FileType::CharDevice  ),
+        _             => None,  // DT_UNKNOWN, the scanner will stat it
+    }
+}
+
+impl Backend for Linux {
+    type Dir = OwnedFd;
+
+    fn stat( &self, at : At<OwnedFd>, follow : bool ) -> io::Result<Stat> {
+        let ( fd, name ) = at_args( at )?;
+        let flags = if follow { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+        let mut st : libc::stat = unsafe { mem::zeroed() };
+        cvt( unsafe { libc::fstatat( fd, name.as_ptr(), &mut st, flags ) } as libc::c_long )?;
+        Ok( Stat { dev: st.st_dev, ino: st.st_ino, mode: st.st_mode, nlink: st.st_nlink as u64,
+                   size: st.st_size as u64, blocks: st.st_blocks as u64, mtime: st.st_mtime as i64,
+                   atime: st.st_atime as i64, uid: st.st_uid, gid: st.st_gid } )
+    }
+
+    fn open_dir( &self, at : At<OwnedFd>, follow : bool ) -> io::Result<OwnedFd> {
+        let ( fd, name ) = at_args( at )?;
+        let mut flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+        if !follow { flags |= libc::O_NOFOLLOW }
+        let dir = cvt( unsafe { libc::openat( fd, name.as_ptr(), flags ) } as libc::c_long )?;
+        Ok( unsafe { OwnedFd::from_raw_fd( dir as RawFd ) } )
+    }
+
+    // struct linux_dirent64 { u64 d_ino; i64 d_off; u16 d_reclen; u8 d_type; char d_name[]; }
+    fn read_dir( &self, dir : &OwnedFd ) -> io::Result<Vec<io::Result<DirEntry>>> {
+        let mut entries = Vec::new();
+        let mut buf     = vec![ 0u8; DENTS_BUF ];
+        loop {
+            let len = cvt( unsafe { libc::syscall( libc::SYS_getdents64, dir.as_raw_fd(),
+                                                   buf.as_mut_ptr(), buf.len() ) } )? as usize;
+            if len == 0 { break }
+
+            let mut pos = 0;
+            while pos < len {
+                let reclen = u16::from_ne_bytes( [ buf[pos+16], buf[pos+17] ] ) as usize;
+                let d_type = buf[pos+18];
+                let name   = &buf[pos+19 .. pos+reclen];
+                let name   = &name[ .. name.iter().position( |&b| b == 0 ).unwrap_or( name.len() ) ];
+                if name != b"." && name != b".." {
+                    entries.push( Ok( DirEntry { name      : OsStr::from_bytes( name ).to_os_string(),
+                                                 file_type : file_type_from_dtype( d_type ) } ) );
+                }
+                pos += reclen;
+            }
+        }
+        Ok( entries )
+    }
+
+    fn read_link( &self, at : At<OwnedFd> ) -> io::Result<PathBuf> {
+        let ( fd, name ) = at_args( at )?;
+        let mut buf = vec![ 0u8; libc::PATH_MAX as usize ];
+        let len = cvt( unsafe { libc::readlinkat( fd, name.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char,
+                                                  buf.len() ) } as libc::c_long )?;
+        buf.truncate( len as usize );
+        Ok( PathBuf::from( OsString::from_vec( buf ) ) )
+    }
+
+    fn read_file( &self, at : At<OwnedFd>, limit : u64 ) -> io::Result<Vec<u8>> {
+        let ( fd, name ) = at_args( at )?;
+        let flags = libc::O_RDONLY | libc::O_CLOEXEC | libc::O_NONBLOCK;   // FIFOs don't hang the scan
+        let file  = cvt( unsafe { libc::openat( fd, name.as_ptr(), flags ) } as libc::c_long )?;
+        let mut buf = Vec::new();
+        unsafe { File::from_raw_fd( file as RawFd ) }.take( limit ).read_to_end( &mut buf )?;
+        Ok( buf )
+    }
+}
+
+// License
+//
+// This script is free software; you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This script is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this script; if not, write to the
+// Free Software Foundation, Inc., 59 Temple Place, Suite 330,
@@ -0,0 +1,97 @@
+//!
+//! Portable backend
+//!
+//! Goes through `std::fs`, opening every entry by its full path
+//!
+
+use super::{At, Backend, DirEntry, FileType, Stat};
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::os::unix::fs::FileTypeExt;
+#[cfg(target_os = "freebsd")]
+use std::os::freebsd::fs::MetadataExt;
+#[cfg(target_os = "linux")]
+use std::os::linux::fs::MetadataExt;
+#[cfg(target_os = "macos")]
+use std::os::unix::fs::MetadataExt;
+
+pub struct Std;
+
+fn path( at : At<PathBuf> ) -> PathBuf {
+    match at {
+        At::Root( path )       => path.to_path_buf(),
+        At::Entry( dir, name ) => dir.join( name ),
+    }
+}
+
+fn stat_from_metadata( metadata : &fs::Metadata ) -> Stat {
+    #[cfg(any(target_os = "freebsd", target_os = "linux"))]
+    return Stat { dev: metadata.st_dev(), ino: metadata.st_ino(), mode: metadata.st_mode(),
+                  nlink: metadata.st_nlink(), size: metadata.st_size(), blocks: metadata.st_blocks(),
+                  mtime: metadata.st_mtime(), atime: metadata.st_atime(), uid: metadata.st_uid(),
+                  gid: metadata.st_gid() };
+    #[cfg(target_os = "macos")]
+    return Stat { dev: metadata.dev(), ino: metadata.ino(), mode: metadata.mode() as u32,
+                  nlink: metadata.nlink(), size: metadata.size(), blocks: metadata.blocks(),
+                  mtime: metadata.mtime(), atime: metadata.atime(), uid: metadata.uid(), gid: metadata.gid() };
+}
+
+fn file_type_from_std( file_type : fs::FileType ) -> Option<FileType> {
+    if      file_type.is_file()         { Some( FileType::File        ) }
+    else if file_type.is_dir()          { Some( FileType::Dir         ) }
+    else if file_type.is_symlink()      { Some( FileType::Symlink     ) }
+    else if file_type.is_fifo()         { Some( FileType::Fifo        ) }
+    else if file_type.is_socket()       { Some( FileType::Socket      ) }
+    else if file_type.is_block_device() { Some( FileType::BlockDevice ) }
+    else if file_type.is_char_device()  { Some( FileType::CharDevice  ) }
+    else                                { None }
+}
+
+impl Backend for Std {
+    type Dir = PathBuf;     // directories are not kept open
+
+    fn stat( &self, at : At<PathBuf>, follow : bool ) -> io::Result<Stat> {
+        let path = path( at );
+        let metadata = if follow { path.metadata()? } else { path.symlink_metadata()? };
+        Ok( stat_from_metadata( &metadata ) )
+    }
+
+    fn open_dir( &self, at : At<PathBuf>, _follow : bool ) -> io::Result<PathBuf> {
+        Ok( path( at ) )
+    }
+
+    fn read_dir( &self, dir : &PathBuf ) -> io::Result<Vec<io::Result<DirEntry>>> {
+        Ok( dir.read_dir()?.map( |entry| entry.map( |entry| DirEntry {
+            name      : entry.file_name(),
+            file_type : entry.file_type().ok().and_then( file_type_from_std ),
+        } ) ).collect() )
+    }
+
+    fn read_link( &self, at : At<PathBuf> ) -> io::Result<PathBuf> {
+        path( at ).read_link()
+    }
+
+    fn read_file( &self, at : At<PathBuf>, limit : u64 ) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        fs::File::open( path( at ) )?.take( limit ).read_to_end( &mut buf )?;
+        Ok( buf )
+    }
+}
+
+// License
+//
+// This script is free software; you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This script is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this script; if not, write to the
+// Free Software Foundation, Inc., 59 Temple Place, Suite 330,
@@ -0,0 +1,145 @@
+//!
+//! io_uring backend
+//!
+//! Same as the Linux backend, but the entries of a directory are stat'ed all at once
+//! by queueing one statx(2) per entry in an io_uring. On slow storage the kernel can
+//! then work on many of them at the same time instead of waiting for each one.
+//! Only built with the io-uring feature
+//!
+
+use super::{At, Backend, DirEntry, Stat};
+use super::linux::Linux;
+
+use io_uring::{opcode, types, IoUring, Probe};
+
+use std::cell::RefCell;
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+const RING_ENTRIES : u32 = 256;
+
+// only what ends up in a Stat
+const STATX_MASK : u32 = libc::STATX_TYPE | libc::STATX_MODE | libc::STATX_NLINK | libc::STATX_INO |
+                         libc::STATX_SIZE | libc::STATX_BLOCKS | libc::STATX_MTIME | libc::STATX_ATIME |
+                         libc::STATX_UID | libc::STATX_GID;
+
+thread_local! {
+    // a ring can't be shared between threads, each scanning thread sets up its own
+    static RING : RefCell<Option<IoUring>> = const { RefCell::new( None ) };
+}
+
+pub struct Uring {
+    linux : Linux,
+}
+
+impl Uring {
+    // None if the kernel has no io_uring or can't statx through it, then the
+    // plain Linux backend is used instead
+    pub fn new() -> Option<Uring> {
+        let ring = IoUring::new( RING_ENTRIES ).ok()?;
+        let mut probe = Probe::new();
+        ring.submitter().register_probe( &mut probe ).ok()?;
+        if !probe.is_supported( opcode::Statx::CODE ) { return None }
+        Some( Uring { linux: Linux::new() } )
+    }
+}
+
+fn stat_from_statx( stx : &libc::statx ) -> Stat {
+    Stat { dev: libc::makedev( stx.stx_dev_major, stx.stx_dev_minor ), ino: stx.stx_ino,
+           mode: stx.stx_mode as u32, nlink: stx.stx_nlink as u64, size: stx.stx_size, blocks: stx.stx_blocks,
+           mtime: stx.stx_mtime.tv_sec, atime: stx.stx_atime.tv_sec, uid: stx.stx_uid, gid: stx.stx_gid }
+}
+
+impl Backend for Uring {
+    type Dir = OwnedFd;
+
+    fn stat( &self, at : At<OwnedFd>, follow : bool ) -> io::Result<Stat> {
+        self.linux.stat( at, follow )
+    }
+
+    fn open_dir( &self, at : At<OwnedFd>, follow : bool ) -> io::Result<OwnedFd> {
+        self.linux.open_dir( at, follow )
+    }
+
+    fn read_dir( &self, dir : &OwnedFd ) -> io::Result<Vec<io::Result<DirEntry>>> {
+        self.linux.read_dir( dir )
+    }
+
+    fn read_link( &self, at : At<OwnedFd> ) -> io::Result<PathBuf> {
+        self.linux.read_link( at )
+    }
+
+    fn read_file( &self, at : At<OwnedFd>, limit : u64 ) -> io::Result<Vec<u8>> {
+        self.linux.read_file( at, limit )
+    }
+
+    fn stat_batch( &self, dir : &OwnedFd, entries : &[( &OsStr, bool )] ) -> Option<Vec<io::Result<Stat>>> {
+        let names : Vec<CString> = entries.iter().map( |( name, _ )| CString::new( name.as_bytes() ) )
+                                          .collect::<Result<_, _>>().ok()?;
+        let mut bufs    : Vec<libc::statx> = vec![ unsafe { mem::zeroed() }; entries.len() ];
+        let mut results : Vec<Option<io::Result<Stat>>> = entries.iter().map( |_| None ).collect();
+
+        RING.with( |ring| {
+            let mut ring = ring.borrow_mut();
+            if ring.is_none() {
+                *ring = IoUring::new( RING_ENTRIES ).ok();
+            }
+            let uring = ring.as_mut()?;
+
+            let ( mut next, mut pending ) = ( 0, 0 );
+            while next < entries.len() || pending > 0 {
+                let mut sq = uring.submission();
+                while next < entries.len() && !sq.is_full() {
+                    let flags = if entries[next].1 { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+                    let sqe = opcode::Statx::new( types::Fd( dir.as_raw_fd() ), names[next].as_ptr(),
+                                                  unsafe { bufs.as_mut_ptr().add( next ) } as *mut types::statx )
+                                  .flags( flags ).mask( STATX_MASK ).build().user_data( next as u64 );
+                    unsafe { sq.push( &sqe ).expect( "submission queue full" ) };
+                    next    += 1;
+                    pending += 1;
+                }
+                drop( sq );
+
+                match uring.submit_and_wait( 1 ) {
+                    Ok( _ ) => {},
+                    Err( err ) if err.kind() == io::ErrorKind::Interrupted => {},
+                    Err( _ ) => {
+                        // the kernel could still write to requests in flight
+                        mem::forget( names );
+                        mem::forget( bufs );
+                        *ring = None;
+                        return None;
+                    },
+                }
+
+                for cqe in uring.completion() {
+                    let i = cqe.user_data() as usize;
+                    results[i] = Some( if cqe.result() < 0 { Err( io::Error::from_raw_os_error( -cqe.result() ) ) }
+                                       else { Ok( stat_from_statx( &bufs[i] ) ) } );
+                    pending -= 1;
+                }
+            }
+            Some( results.into_iter().map( Option::unwrap ).collect() )
+        } )
+    }
+}
+
+// License
+//
+// This script is free software; you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This script is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this script; if not, write to the
+// Free Software Foundation, Inc., 59 Temple Place, Suite 330,
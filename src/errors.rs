@@ -0,0 +1,131 @@
+//!
+//! Errors found while scanning
+//!
+//! Scanning threads collect them as they go, and they are reported together once
+//! the tree has been printed
+//!
+
+use crate::fmt_os_str;
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::collections::BTreeMap;
+
+// paths listed by --errors=summary
+const SUMMARY_PATHS : usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ErrorKind {
+    Os( i32 ),             // errno, most errors come with one
+    Io( io::ErrorKind ),
+    SymlinkLoop,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            ErrorKind::Os( code )   => {
+                // the std text without its " (os error N)" suffix
+                let text = io::Error::from_raw_os_error( *code ).to_string();
+                write!( f, "{}", text.split( " (os error" ).next().unwrap_or( &text ) )
+            },
+            ErrorKind::Io( kind )   => write!( f, "{}", kind ),
+            ErrorKind::SymlinkLoop => write!( f, "symlink loop" ),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct ScanError {
+    pub path : PathBuf,
+    pub kind : ErrorKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorMode {
+    Summary,    // counts per kind and the first paths
+    Full,       // every path
+    Quiet,      // only the exit status
+}
+
+impl ErrorMode {
+    pub fn parse( mode : &str ) -> Option<ErrorMode> {
+        match mode {
+            "summary" => Some( ErrorMode::Summary ),
+            "full"    => Some( ErrorMode::Full ),
+            "quiet"   => Some( ErrorMode::Quiet ),
+            _         => None,
+        }
+    }
+}
+
+// shared by all scanning threads
+#[derive(Default)]
+pub struct Errors {
+    list : Mutex<Vec<ScanError>>,
+}
+
+impl Errors {
+    pub fn push( &self, path : &Path, kind : ErrorKind ) {
+        let error = ScanError { path: path.to_path_buf(), kind };
+        self.list.lock().unwrap().push( error );
+    }
+
+    pub fn push_io( &self, path : &Path, err : &io::Error ) {
+        let kind = match err.raw_os_error() {
+            Some( code ) => ErrorKind::Os( code ),
+            None         => ErrorKind::Io( err.kind() ),
+        };
+        self.push( path, kind )
+    }
+
+    pub fn is_empty( &self ) -> bool {
+        self.list.lock().unwrap().is_empty()
+    }
+
+    // sorted by path, so the report doesn't depend on thread scheduling
+    pub fn sorted( &self ) -> Vec<ScanError> {
+        let mut list = std::mem::take( &mut *self.list.lock().unwrap() );
+        list.sort();
+        list
+    }
+
+    pub fn report( &self, mode : ErrorMode ) {
+        if mode == ErrorMode::Quiet { return }
+        let list = self.sorted();
+        if list.is_empty() { return }
+
+        let mut counts = BTreeMap::new();
+        for error in &list {
+            *counts.entry( error.kind ).or_insert( 0 ) += 1;
+        }
+        let counts : Vec<String> = counts.iter().map( |( kind, n )| format!( "{} {}", n, kind ) ).collect();
+        eprintln!( "Couldn't read {} paths ({})", list.len(), counts.join( ", " ) );
+
+        let shown = if mode == ErrorMode::Full { list.len() } else { SUMMARY_PATHS };
+        for error in list.iter().take( shown ) {
+            eprintln!( "  {} ({})", fmt_os_str( error.path.as_os_str() ), error.kind );
+        }
+        if list.len() > shown {
+            eprintln!( "  ... and {} more, use --errors=full to list them all", list.len() - shown );
+        }
+    }
+}
+
+// License
+//
+// This script is free software; you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This script is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this script; if not, write to the
+// Free Software Foundation, Inc., 59 Temple Place, Suite 330,
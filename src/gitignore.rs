@@ -0,0 +1,157 @@
+//!
+//! .gitignore, .ignore and .dutreeignore files
+//!
+//! Each directory the scanner reads adds its own ignore files on top of the ones from the
+//! directories above, so that deeper files take precedence like they do for git. For git,
+//! scan roots start from the global excludes file and the ignore files above them, up to the
+//! top of their repository. .dutreeignore files are only read from the scan roots down
+//!
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// in increasing precedence
+const IGNORE_FILES : [&str; 3] = [ ".git/info/exclude", ".gitignore", ".ignore" ];
+const DUTREE_FILES : [&str; 1] = [ ".dutreeignore" ];
+
+// the ignore files of one directory, or the global excludes
+struct Matcher {
+    base      : PathBuf,    // patterns are relative to this directory
+    gitignore : Gitignore,
+}
+
+impl Matcher {
+    // None for paths not below base
+    fn matched( &self, path : &Path, is_dir : bool ) -> Option<bool> {
+        let rel = path.strip_prefix( &self.base ).ok().filter( |rel| !rel.as_os_str().is_empty() )?;
+        match self.gitignore.matched( rel, is_dir ) {
+            Match::Ignore( _ )    => Some( true ),
+            Match::Whitelist( _ ) => Some( false ),
+            Match::None           => None,
+        }
+    }
+}
+
+// what applies to the entries of a directory
+#[derive(Clone)]
+pub struct Ignores {
+    root  : Arc<Path>,              // absolute path of the scan root
+    dir   : PathBuf,                // absolute path of the directory, only while there are patterns to match
+    stack : Vec<Arc<Matcher>>,      // the global excludes first, then from the outermost directory in
+    all   : bool,                   // the directory is ignored, and so everything in it
+    files : &'static [&'static str],
+}
+
+impl Ignores {
+    // for a scan root, before reading the ignore files in it
+    pub fn root( path : &Path ) -> Ignores {
+        let files = &IGNORE_FILES;
+        let dir = absolute( path );
+        let above : Vec<&Path> = dir.ancestors().skip( 1 ).collect();
+        let top = above.iter().position( |d| d.join( ".git" ).exists() );
+
+        let mut stack = Vec::new();
+        let ( global, _ ) = Gitignore::global();
+        if !global.is_empty() {
+            let base = top.map_or( dir.as_path(), |top| above[top] ).to_path_buf();
+            stack.push( Arc::new( Matcher { base, gitignore: global } ) );
+        }
+        if let Some( top ) = top {
+            for d in above[ ..= top ].iter().rev() {
+                stack.extend( load( d, read_files( files, |file| fs::read( d.join( file ) ).ok() ) ) );
+            }
+        }
+
+        let mut ignores = Ignores { root: Arc::from( dir.as_path() ), dir: PathBuf::new(), stack, all: false, files };
+        ignores.all = dir.ancestors().take( top.map_or( 1, |top| top + 1 ) )
+                         .any( |d| ignores.matched( d, true ) );
+        ignores.dir = dir;
+        ignores
+    }
+
+    // for a scan root, with .dutreeignore files instead
+    pub fn dutree( path : &Path ) -> Ignores {
+        let dir = absolute( path );
+        Ignores { root: Arc::from( dir.as_path() ), dir, stack: Vec::new(), all: false, files: &DUTREE_FILES }
+    }
+
+    // for the entries of a directory. rel gives its path relative to the scan root, only
+    // asked for if there are patterns to match. read gives the contents of a file in it,
+    // reached from the open directory rather than by path
+    pub fn enter<R, F>( &self, rel : R, ignored : bool, read : F ) -> Ignores
+        where R : FnOnce() -> PathBuf, F : Fn( &str ) -> Option<Vec<u8>> {
+        let texts = read_files( self.files, read );
+        let mut ignores = Ignores { root: self.root.clone(), dir: PathBuf::new(), stack: self.stack.clone(),
+                                    all: self.all || ignored, files: self.files };
+        if !ignores.stack.is_empty() || !texts.is_empty() {
+            let rel = rel();
+            ignores.dir = if rel.as_os_str().is_empty() { self.root.to_path_buf() } else { self.root.join( rel ) };
+            ignores.stack.extend( load( &ignores.dir, texts ) );
+        }
+        ignores
+    }
+
+    // name is None for the directory itself, like for enter. Git never tracks what's in .git
+    pub fn is_ignored( &self, name : Option<&OsStr>, is_dir : bool ) -> bool {
+        match name {
+            Some( name ) if name == ".git" && self.files == IGNORE_FILES => true,
+            Some( _ ) if self.stack.is_empty() => self.all,     // no patterns, no path to build
+            Some( name ) => self.all || self.matched( &self.dir.join( name ), is_dir ),
+            None         => self.all,
+        }
+    }
+
+    // the deepest ignore file with a pattern for the path decides
+    fn matched( &self, path : &Path, is_dir : bool ) -> bool {
+        self.stack.iter().rev().find_map( |m| m.matched( path, is_dir ) ).unwrap_or( false )
+    }
+}
+
+// the ones of files there are, with what's in them
+fn read_files<F : Fn( &str ) -> Option<Vec<u8>>>( files : &[&'static str], read : F ) -> Vec<( &'static str, Vec<u8> )> {
+    files.iter().filter_map( |file| Some( ( *file, read( file )? ) ) ).collect()
+}
+
+fn load( dir : &Path, texts : Vec<( &str, Vec<u8> )> ) -> Option<Arc<Matcher>> {
+    if texts.is_empty() { return None }
+    let mut builder = GitignoreBuilder::new( dir );
+    for ( file, text ) in texts {
+        let from = dir.join( file );
+        let text = String::from_utf8_lossy( &text );
+        for line in text.trim_start_matches( '\u{feff}' ).lines() {
+            let _ = builder.add_line( Some( from.clone() ), line );  // lines that can't be parsed are skipped
+        }
+    }
+    let gitignore = builder.build().ok().filter( |gitignore| !gitignore.is_empty() )?;
+    Some( Arc::new( Matcher { base: dir.to_path_buf(), gitignore } ) )
+}
+
+// without resolving the last component, so a link given as root is matched as a link
+fn absolute( path : &Path ) -> PathBuf {
+    let abs = std::env::current_dir().unwrap_or_default().join( path );
+    match ( abs.parent(), abs.file_name() ) {
+        ( Some( parent ), Some( name ) ) => parent.canonicalize().unwrap_or_else( |_| parent.to_path_buf() ).join( name ),
+        _                                => abs.canonicalize().unwrap_or( abs ),  // / and paths ending in ..
+    }
+}
+
+// License
+//
+// This script is free software; you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This script is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this script; if not, write to the
+// Free Software Foundation, Inc., 59 Temple Place, Suite 330,
@@ -12,10 +12,59 @@
 //!     -a, --aggr [N[KMG]] aggregate smaller than N B/KiB/MiB/GiB (def 1M)
 //!     -s, --summary       equivalent to -da, or -d1 -a1M
 //!     -u, --usage         report real disk usage instead of file size
+//!         --both          show file size and disk usage, sort by -u choice
+//!         --sparse        report sparse file holes and unused block slack
+//!         --inodes        count files, dirs and links instead of bytes; -a
+//!                         counts them too (def 1K)
 //!     -b, --bytes         print sizes in bytes
-//!     -x, --exclude NAME  exclude matching files or directories
+//!     -l, --count-links   count sizes many times if hard linked
+//!     -f, --files-only    skip directories for a fast local overview
+//!     -x, --exclude PATTERN
+//!                         exclude files or directories matching a glob
+//!         --exclude-from FILE
+//!                         exclude the globs listed in FILE, one per line
+//!         --exclude-regex REGEX
+//!                         exclude paths below the root matching REGEX
+//!         --include-regex REGEX
+//!                         only count files with paths matching REGEX
+//!         --respect-gitignore 
+//!                         exclude files ignored by .gitignore or .ignore files
+//!         --only-ignored  only count files ignored by .gitignore or .ignore
+//!                         files
+//!         --older-than WHEN
+//!                         only count files modified before WHEN, a time ago
+//!                         (e.g. 180d) or a UTC date (e.g. 2024-01-31)
+//!         --newer-than WHEN
+//!                         only count files modified after WHEN
+//!         --accessed-before WHEN
+//!                         only count files last accessed before WHEN
+//!         --min-size N[KMG]
+//!                         only count files of at least N B/KiB/MiB/GiB
+//!         --max-size N[KMG]
+//!                         only count files of at most N B/KiB/MiB/GiB
+//!         --type TYPES    only count entries of these types, comma separated: f
+//!                         file, d dir, l symlink, p fifo, s socket, b block or c
+//!                         char device
+//!         --user USER     only count files owned by USER, a name or a uid
+//!         --group GROUP   only count files of GROUP, a name or a gid
+//!         --by-owner      break down directories by owner
+//!         --exclude-caches [collapse]
+//!                         exclude directories tagged with CACHEDIR.TAG, or with
+//!                         =collapse show them without their contents
 //!     -H, --no-hidden     exclude hidden files
+//!         --one-file-system 
+//!                         skip directories on different file systems
+//!     -L, --follow-symlinks 
+//!                         follow all symbolic links
+//!         --follow-cmdline 
+//!                         follow symbolic links given as arguments
 //!     -A, --ascii         ASCII characters only, no colors
+//!     -j, --threads N     scan using N threads (def: number of CPUs)
+//!         --errors MODE   report unreadable paths: summary, full or quiet
+//!         --progress WHEN show scan progress on stderr: auto, always or never
+//!         --max-time DURATION
+//!                         stop scanning after DURATION (e.g. 500ms, 30s, 5m)
+//!         --max-entries N stop scanning after N files and directories
 //!     -h, --help          show help
 //!     -v, --version       print version number
 //! ```
@@ -40,6 +89,9 @@ use unicode_width::UnicodeWidthStr;
 extern crate unicode_segmentation;
 use unicode_segmentation::UnicodeSegmentation;
 
+extern crate globset;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
 extern crate getopts;
 use getopts::Options;
 
@@ -48,22 +100,37 @@ use terminal_size::{Width, Height, terminal_size};
 
 extern crate regex;
 use regex::Regex;
+use regex::bytes::RegexSet;
+
+extern crate rayon;
+
+mod scan;
+use scan::{Node, ScanState};
+use backend::FileType;
+pub use scan::interrupt;
+mod errors;
+use errors::ErrorMode;
+mod progress;
+mod backend;
+mod gitignore;
+mod accounts;
+use accounts::Names;
 
-use std::io;
 use std::path::{Path, PathBuf};
-use std::fs;
-#[cfg(target_os = "freebsd")]
-use std::os::freebsd::fs::MetadataExt;
-#[cfg(target_os = "linux")]
-use std::os::linux::fs::MetadataExt;
-#[cfg(target_os = "macos")]
-use std::os::unix::fs::MetadataExt;
 use std::env;
-use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::collections::{HashMap, HashSet};
 
 const VERSTR    : &str = env!("CARGO_PKG_VERSION");
 const DEF_WIDTH : u16  = 80;
 
+// the scan keeps a stack of its own, but pruning the tree for display still recurses once
+// per directory level on the pool threads. Only the pages actually used get memory
+const STACK_SIZE : usize = 64 * 1024 * 1024;
+
 pub enum XResult<T,S> {
     XOk(T),
     XErr(S),
@@ -72,11 +139,70 @@ pub enum XResult<T,S> {
 use XResult::{XOk, XExit, XErr};
 
 struct Entry<'a> {
-    name    : String,
-    bytes   : u64,
+    name    : OsString,  // raw bytes, only escaped when printed
+    bytes   : u64,      // file size, or disk usage with -u. Used to sort and plot
+    sizes   : Sizes,
     color   : Option<&'a str>,
     last    : bool,
     entries : Option<Vec<Entry<'a>>>,
+    tag     : Option<&'static str>,
+    target  : Option<PathBuf>,
+    leaf    : bool,     // a regular file, see waste_flag
+}
+
+// everything added up for a file or a directory tree
+#[derive(Clone, Copy, Default)]
+struct Sizes {
+    apparent : u64,
+    usage    : u64,
+    holes    : u64,     // file size not allocated on disk, in sparse files
+    slack    : u64,     // disk allocated beyond the file size
+    inodes   : u64,     // files, directories and links
+    partial  : bool,    // some of it wasn't scanned, sizes are lower bounds
+    matched  : bool,    // holds something other than directories, see is_emptied
+}
+
+impl std::ops::AddAssign for Sizes {
+    fn add_assign( &mut self, other : Sizes ) {
+        self.apparent += other.apparent;
+        self.usage    += other.usage;
+        self.holes    += other.holes;
+        self.slack    += other.slack;
+        self.inodes   += other.inodes;
+        self.partial  |= other.partial;
+        self.matched  |= other.matched;
+    }
+}
+
+impl Sizes {
+    // the size used to sort, aggregate and plot, or the inode count with --inodes
+    fn bytes( &self, cfg : &Config ) -> u64 {
+        if cfg.inodes_flag { self.inodes } else { self.size( cfg ) }
+    }
+
+    fn size( &self, cfg : &Config ) -> u64 {
+        if cfg.usage_flag { self.usage } else { self.apparent }
+    }
+}
+
+// sizes per owner uid, only kept with --by-owner
+#[derive(Default)]
+struct Owners( HashMap<u32, Sizes> );
+
+impl Owners {
+    fn add( &mut self, uid : u32, sizes : Sizes ) {
+        *self.0.entry( uid ).or_default() += sizes;
+    }
+
+    fn merge( &mut self, other : Owners ) {
+        other.0.into_iter().for_each( |( uid, sizes )| self.add( uid, sizes ) );
+    }
+}
+
+// hard links already counted while pruning, and the bytes they would have added again
+struct Dedup {
+    seen  : HashSet<(u64, u64)>,
+    saved : u64,
 }
 
 pub struct Config {
@@ -86,11 +212,39 @@ pub struct Config {
     depth_flag  : bool,
     bytes_flag  : bool,
     usage_flag  : bool,
+    both_flag   : bool,
+    sparse_flag : bool,
+    inodes_flag : bool,
     hiddn_flag  : bool,
     ascii_flag  : bool,
     no_dir_flg  : bool,
     aggr        : u64,
-    exclude     : Vec<String>,
+    exclude     : GlobSet,
+    exclude_re  : RegexSet,
+    include_re  : RegexSet,
+    ignore_flag : bool,
+    only_ignore : bool,
+    older_than  : Option<i64>,  // seconds since the epoch
+    newer_than  : Option<i64>,
+    atime_older : Option<i64>,
+    min_size    : Option<u64>,
+    max_size    : Option<u64>,
+    types       : Vec<FileType>,
+    users       : Vec<u32>,
+    groups      : Vec<u32>,
+    owner_flag  : bool,
+    user_names  : Names,    // only read with --user or --by-owner
+    cache_flag  : bool,
+    cache_fold  : bool,     // show caches collapsed instead of leaving them out
+    threads     : usize,
+    links_flag  : bool,
+    xdev_flag   : bool,
+    follow_flag : bool,
+    follow_root : bool,
+    errors      : ErrorMode,
+    progress    : bool,
+    max_time    : Option<Duration>,
+    max_entries : Option<u64>,
 }
 
 fn init_opts() -> Options {
@@ -100,11 +254,38 @@ fn init_opts() -> Options {
     options.optflagopt( "a", "aggr"     , "aggregate smaller than N B/KiB/MiB/GiB (def 1M)", "N[KMG]");
     options.optflag(    "s", "summary"  , "equivalent to -da, or -d1 -a1M"                );
     options.optflag(    "u", "usage"    , "report real disk usage instead of file size"   );
+    options.optflag(    "" , "both"     , "show file size and disk usage, sort by -u choice");
+    options.optflag(    "" , "sparse"   , "report sparse file holes and unused block slack" );
+    options.optflag(    "" , "inodes"   , "count files, dirs and links instead of bytes; -a counts them too (def 1K)" );
     options.optflag(    "b", "bytes"    , "print sizes in bytes"                          );
+    options.optflag(    "l", "count-links","count sizes many times if hard linked"        );
     options.optflag(    "f", "files-only","skip directories for a fast local overview"    );
-    options.optmulti(   "x", "exclude"  , "exclude matching files or directories", "NAME" );
+    options.optmulti(   "x", "exclude"  , "exclude files or directories matching a glob", "PATTERN" );
+    options.optmulti(   "" , "exclude-from", "exclude the globs listed in FILE, one per line", "FILE" );
+    options.optmulti(   "" , "exclude-regex", "exclude paths below the root matching REGEX", "REGEX" );
+    options.optmulti(   "" , "include-regex", "only count files with paths matching REGEX", "REGEX" );
+    options.optflag(    "" , "respect-gitignore", "exclude files ignored by .gitignore or .ignore files" );
+    options.optflag(    "" , "only-ignored", "only count files ignored by .gitignore or .ignore files" );
+    options.optopt(     "" , "older-than", "only count files modified before WHEN, a time ago (e.g. 180d) or a UTC date (e.g. 2024-01-31)", "WHEN" );
+    options.optopt(     "" , "newer-than", "only count files modified after WHEN", "WHEN" );
+    options.optopt(     "" , "accessed-before", "only count files last accessed before WHEN", "WHEN" );
+    options.optopt(     "" , "min-size" , "only count files of at least N B/KiB/MiB/GiB", "N[KMG]" );
+    options.optopt(     "" , "max-size" , "only count files of at most N B/KiB/MiB/GiB", "N[KMG]" );
+    options.optmulti(   "" , "type"     , "only count entries of these types, comma separated: f file, d dir, l symlink, p fifo, s socket, b block or c char device", "TYPES" );
+    options.optmulti(   "" , "user"     , "only count files owned by USER, a name or a uid", "USER" );
+    options.optmulti(   "" , "group"    , "only count files of GROUP, a name or a gid", "GROUP" );
+    options.optflag(    "" , "by-owner" , "break down directories by owner"                );
+    options.optflagopt( "" , "exclude-caches", "exclude directories tagged with CACHEDIR.TAG, or with =collapse show them without their contents", "collapse" );
     options.optflag(    "H", "no-hidden", "exclude hidden files"                          );
+    options.optflag(    "" , "one-file-system", "skip directories on different file systems");
+    options.optflag(    "L", "follow-symlinks", "follow all symbolic links"               );
+    options.optflag(    "" , "follow-cmdline" , "follow symbolic links given as arguments");
     options.optflag(    "A", "ascii"    , "ASCII characters only, no colors"              );
+    options.optopt(     "j", "threads"  , "scan using N threads (def: number of CPUs)", "N" );
+    options.optopt(     "" , "errors"   , "report unreadable paths: summary, full or quiet", "MODE" );
+    options.optopt(     "" , "progress" , "show scan progress on stderr: auto, always or never", "WHEN" );
+    options.optopt(     "" , "max-time" , "stop scanning after DURATION (e.g. 500ms, 30s, 5m)", "DURATION" );
+    options.optopt(     "" , "max-entries", "stop scanning after N files and directories", "N" );
     options.optflag(    "h", "help"     , "show help"                                     );
     options.optflag(    "v", "version"  , "print version number"                          );
     options
@@ -112,8 +293,10 @@ fn init_opts() -> Options {
 
 impl Config {
     pub fn new() -> XResult<Config, String> {
+        Config::from_args( env::args().collect() )
+    }
 
-        let args: Vec<String> = env::args().collect();
+    fn from_args( args : Vec<String> ) -> XResult<Config, String> {
         let program = args[0].clone();
 
         let options = init_opts();
@@ -134,14 +317,14 @@ impl Config {
         let color_dict = create_color_dict();
 
         let mut paths : Vec<PathBuf> = Vec::new();
-        if opt.free.len() == 0 {
+        if opt.free.is_empty() {
             let mut path = std::path::PathBuf::new();
-            path.push( ".".to_string() );
+            path.push( "." );
             paths.push( path );
         } else {
             for opt in &opt.free {
                 let mut path = std::path::PathBuf::new();
-                path.push( &opt );
+                path.push( opt );
                 paths.push( path );
             }
         }
@@ -154,178 +337,422 @@ impl Config {
 
         let mut depth_flag = opt.opt_present("d");
         let depth_opt = opt.opt_str("d");
-        let mut depth = depth_opt.unwrap_or("1".to_string()).parse().unwrap_or(1);
+        let mut depth = depth_opt.unwrap_or_else(|| "1".to_string()).parse().unwrap_or(1);
 
         let bytes_flag = opt.opt_present("b");
         let usage_flag = opt.opt_present("u");
+        let both_flag  = opt.opt_present("both");
+        let sparse_flag = opt.opt_present("sparse");
+        let inodes_flag = opt.opt_present("inodes");
+        let def_aggr    = if inodes_flag { "1K" } else { "1M" };
+        let links_flag = opt.opt_present("l");
         let hiddn_flag = opt.opt_present("H");
         let ascii_flag = opt.opt_present("A");
         let no_dir_flg = opt.opt_present("f");
+        let xdev_flag  = opt.opt_present("one-file-system");
+        let follow_flag = opt.opt_present("L");
+        let follow_root = opt.opt_present("follow-cmdline");
+        let ignore_flag = opt.opt_present("respect-gitignore");
+        let only_ignore = opt.opt_present("only-ignored");
+        if ignore_flag && only_ignore {
+            return XErr( "--respect-gitignore and --only-ignored can't be used together".to_string() );
+        }
 
         let mut aggr = if opt.opt_present("a") {
             let aggr_opt = opt.opt_str("a");
-            let aggr_val = aggr_opt.unwrap_or("1M".to_string());
+            let aggr_val = aggr_opt.unwrap_or_else(|| def_aggr.to_string());
+            match parse_size( &aggr_val ) {
+                Some(aggr) => aggr,
+                None       => return XErr( format!( "invalid argument '{}'", aggr_val ) ),
+            }
+        } else {
+            0
+        };
 
-            if !Regex::new(r"^\d+\D?$").unwrap().is_match( aggr_val.as_str() ){
-                return XErr( format!( "invalid argument '{}'", aggr_val ) );
+        let mut sizes = Vec::new();
+        for name in &[ "min-size", "max-size" ] {
+            sizes.push( match opt.opt_str( name ) {
+                Some(size) => match parse_size( &size ) {
+                    Some(size) => Some(size),
+                    None       => return XErr( format!( "invalid argument '{}'", size ) ),
+                },
+                None => None,
+            } );
+        }
+        let ( min_size, max_size ) = ( sizes[0], sizes[1] );
+
+        let owner_flag = opt.opt_present("by-owner");
+        let user_names = if owner_flag || opt.opt_present("user") { Names::users() } else { Names::default() };
+        let mut users = Vec::new();
+        for user in opt.opt_strs("user") {
+            match user_names.id( &user ) {
+                Some(uid) => users.push( uid ),
+                None      => return XErr( format!( "invalid argument '{}'", user ) ),
+            }
+        }
+        let group_names = if opt.opt_present("group") { Names::groups() } else { Names::default() };
+        let mut groups = Vec::new();
+        for group in opt.opt_strs("group") {
+            match group_names.id( &group ) {
+                Some(gid) => groups.push( gid ),
+                None      => return XErr( format!( "invalid argument '{}'", group ) ),
             }
+        }
 
-            let unit = aggr_val.matches(char::is_alphabetic).next().unwrap_or("B");
-            let num : Vec<&str> = aggr_val.matches(char::is_numeric).collect();
-            let num : u64       = num.concat().parse().unwrap();
+        let mut types = Vec::new();
+        for letters in opt.opt_strs("type") {
+            match parse_types( &letters ) {
+                Some(more) => types.extend( more ),
+                None       => return XErr( format!( "invalid argument '{}'", letters ) ),
+            }
+        }
 
-            let factor = match unit {
-                "b" | "B" => 1024u64.pow(0),
-                "k" | "K" => 1024u64.pow(1),
-                "m" | "M" => 1024u64.pow(2),
-                "g" | "G" => 1024u64.pow(3),
-                "t" | "T" => 1024u64.pow(4),
-                _         => 1024u64.pow(0),
+        let mut patterns = opt.opt_strs("x");
+        for file in opt.opt_strs("exclude-from") {
+            match std::fs::read_to_string( &file ) {
+                Ok(text) => patterns.extend( text.lines().map( str::trim_end )
+                                                 .filter( |line| !line.is_empty() && !line.starts_with( '#' ) )
+                                                 .map( String::from ) ),
+                Err(err) => return XErr( format!( "can't read {}: {}", file, err ) ),
+            }
+        }
+        let mut exclude = GlobSetBuilder::new();
+        for pattern in patterns {
+            // * and ? stop at /, so patterns with a / only match paths below the root
+            match GlobBuilder::new( &pattern ).literal_separator( true ).build() {
+                Ok(glob) => exclude.add( glob ),
+                Err(_)   => return XErr( format!( "invalid argument '{}'", pattern ) ),
             };
-            num * factor
-        } else {
-            0
+        }
+        let exclude = exclude.build().unwrap();
+
+        let exclude_re = match RegexSet::new( opt.opt_strs("exclude-regex") ) {
+            Ok(set)  => set,
+            Err(err) => return XErr( format!( "invalid argument: {}", err ) ),
+        };
+        let include_re = match RegexSet::new( opt.opt_strs("include-regex") ) {
+            Ok(set)  => set,
+            Err(err) => return XErr( format!( "invalid argument: {}", err ) ),
         };
 
-        let exclude = opt.opt_strs("x");
+        let now = SystemTime::now();
+        let mut times = Vec::new();
+        for name in &[ "older-than", "newer-than", "accessed-before" ] {
+            times.push( match opt.opt_str( name ) {
+                Some(time) => match parse_time( &time, now ) {
+                    Some(time) => Some(time),
+                    None       => return XErr( format!( "invalid argument '{}'", time ) ),
+                },
+                None => None,
+            } );
+        }
+        let ( older_than, newer_than, atime_older ) = ( times[0], times[1], times[2] );
+
+        let threads = match opt.opt_str("j") {
+            Some(n) => match n.parse() {
+                Ok(n)  => n,
+                Err(_) => return XErr( format!( "invalid argument '{}'", n ) ),
+            },
+            None => 0, // let rayon pick one thread per CPU
+        };
+
+        let max_time = match opt.opt_str("max-time") {
+            Some(time) => match parse_duration( &time ) {
+                Some(time) => Some(time),
+                None       => return XErr( format!( "invalid argument '{}'", time ) ),
+            },
+            None => None,
+        };
+
+        let max_entries = match opt.opt_str("max-entries") {
+            Some(n) => match n.parse() {
+                Ok(n)  => Some(n),
+                Err(_) => return XErr( format!( "invalid argument '{}'", n ) ),
+            },
+            None => None,
+        };
+
+        let errors = match opt.opt_str("errors") {
+            Some(mode) => match ErrorMode::parse( &mode ) {
+                Some(mode) => mode,
+                None       => return XErr( format!( "invalid argument '{}'", mode ) ),
+            },
+            None => ErrorMode::Summary,
+        };
+
+        let ( cache_flag, cache_fold ) = match opt.opt_str("exclude-caches").as_deref() {
+            None              => ( opt.opt_present("exclude-caches"), false ),
+            Some("collapse")  => ( true, true ),
+            Some(how)         => return XErr( format!( "invalid argument '{}'", how ) ),
+        };
+
+        let progress = match opt.opt_str("progress").as_deref() {
+            None | Some("auto") => std::io::stderr().is_terminal(),
+            Some("always")      => true,
+            Some("never")       => false,
+            Some(when)          => return XErr( format!( "invalid argument '{}'", when ) ),
+        };
 
         if opt.opt_present("s") {
             depth_flag = true;
             depth      = 1;
-            aggr       = 1024u64.pow(2);
+            aggr       = if inodes_flag { 1024 } else { 1024u64.pow(2) };
         }
 
         XOk( Config{ paths, color_dict, depth, depth_flag, bytes_flag,
-            usage_flag, hiddn_flag, ascii_flag, no_dir_flg,  aggr, exclude } )
+            usage_flag, both_flag, sparse_flag, inodes_flag, hiddn_flag, ascii_flag, no_dir_flg,  aggr, exclude, threads,
+            exclude_re, include_re, ignore_flag, only_ignore, older_than, newer_than, atime_older,
+            min_size, max_size, types, users, groups, owner_flag, user_names,
+            cache_flag, cache_fold,
+            links_flag, xdev_flag, follow_flag, follow_root, errors, progress,
+            max_time, max_entries } )
+    }
+
+    // number of columns printed by Entry::fmt_size_columns
+    fn size_columns( &self ) -> usize {
+        let sizes  = if self.both_flag { 2 } else if self.inodes_flag { 0 } else { 1 };
+        let inodes = if self.inodes_flag { 1 } else { 0 };
+        let sparse = if self.sparse_flag { 2 } else { 0 };
+        sizes + inodes + sparse
     }
 }
 
-fn try_is_symlink( path : &Path ) -> bool {
-    let metadata = path.symlink_metadata();
-    metadata.is_ok() && metadata.unwrap().file_type().is_symlink()
+// entries the scan leaves out by name, or by path relative to the scan root. dir gives the
+// path of their directory relative to the root, only asked for if there are patterns
+fn is_skipped<'a>( name : &OsStr, dir : impl FnOnce() -> &'a Path, cfg : &Config ) -> bool {
+    ( cfg.hiddn_flag && name.as_bytes().starts_with( b"." ) ) || cfg.exclude.is_match( name ) ||
+        ( ( !cfg.exclude.is_empty() || !cfg.exclude_re.is_empty() ) && {
+            let rel = dir().join( name );
+            cfg.exclude.is_match( &rel ) || cfg.exclude_re.is_match( rel.as_os_str().as_bytes() )
+        } )
 }
 
-fn file_name_from_path( path : &Path ) -> String {
-    let mut abspath = std::env::current_dir().unwrap();
-    abspath.push( path );
+// what the scan kept but is left out of the tree all the same. rel is the path of the
+// node relative to its scan root
+fn is_excluded( node : &Node, rel : &Path, cfg : &Config ) -> bool {
+    ( !node.is_dir && !cfg.include_re.is_empty() && !cfg.include_re.is_match( rel.as_os_str().as_bytes() ) ) ||
+        node.excluded || ( node.cache && !cfg.cache_fold ) ||
+        ( cfg.ignore_flag && node.ignored ) || ( cfg.only_ignore && !node.is_dir && !node.ignored ) ||
+        ( !node.is_dir && !is_counted( node, cfg ) ) ||
+        ( cfg.no_dir_flg && node.is_dir )
+}
 
-    // don't resolve links
-    if !try_is_symlink( path ) {
-        abspath = abspath.canonicalize().unwrap_or( abspath );
-    }
+// filters that only decide what adds to the totals. Files that don't pass are left out,
+// directories stay so the whole structure is shown, only without their own size
+fn is_counted( node : &Node, cfg : &Config ) -> bool {
+    cfg.older_than.is_none_or( |time| node.mtime < time ) &&
+        cfg.newer_than.is_none_or( |time| node.mtime >= time ) &&
+        cfg.atime_older.is_none_or( |time| node.atime < time ) &&
+        cfg.min_size.is_none_or( |size| node_size( node, cfg ) >= size ) &&
+        cfg.max_size.is_none_or( |size| node_size( node, cfg ) <= size ) &&
+        ( cfg.types.is_empty() || node.file_type.is_some_and( |t| cfg.types.contains( &t ) ) ) &&
+        ( cfg.users.is_empty() || cfg.users.contains( &node.uid ) ) &&
+        ( cfg.groups.is_empty() || cfg.groups.contains( &node.gid ) )
+}
 
-    abspath.file_name().unwrap_or( std::ffi::OsStr::new( "/" ) )  // '/' has no filename
-           .to_str().unwrap_or( "[invalid name]" ).to_string()
+fn node_size( node : &Node, cfg : &Config ) -> u64 {
+    if cfg.usage_flag { node.usage } else { node.apparent }
 }
 
-fn try_read_dir( path : &Path ) -> Option<fs::ReadDir> {
-    if try_is_symlink( path ) { return None } // don't follow symlinks
-    match path.read_dir() {
-        Ok(dir_list) => Some(dir_list),
-        Err(err)     => {
-            print_io_error( path, err );
-            None
-        },
+// sizes of a node and everything below it that passes the filters
+fn total_sizes( node : &Node, rel : &mut PathBuf, cfg : &Config, dedup : &mut Dedup, owners : &mut Owners ) -> Sizes {
+    let mut sizes = own_sizes( node, cfg, dedup, owners );
+    if let Some( ref children ) = node.children {
+        for child in children {
+            rel.push( &child.name );
+            if !is_excluded( child, rel, cfg ) {
+                let mut child_owners = Owners::default();
+                let child_sizes = total_sizes( child, rel, cfg, dedup, &mut child_owners );
+                if !is_emptied( child, &child_sizes, cfg ) {
+                    sizes += child_sizes;
+                    owners.merge( child_owners );
+                }
+            }
+            rel.pop();
+        }
     }
+    sizes
 }
 
-fn try_bytes_from_path( path : &Path, usage_flag : bool ) -> u64 {
+// directories left with nothing --include-regex or --only-ignored matched are dropped
+// too, so that only the ones leading to matching files are shown
+fn is_emptied( node : &Node, sizes : &Sizes, cfg : &Config ) -> bool {
+    node.is_dir && !sizes.matched && ( !cfg.include_re.is_empty() || cfg.only_ignore )
+}
 
-    match path.symlink_metadata() {
-        #[cfg(any(target_os = "freebsd", target_os = "linux"))]
-        Ok(metadata) => if usage_flag { metadata.st_blocks()*512 } else { metadata.st_size() },
-        #[cfg(target_os = "macos")]
-        Ok(metadata) => if usage_flag { metadata.blocks()*512 } else { metadata.size() },
-        Err(err)     => {
-            print_io_error( path, err );
-            0
-        },
+// hard links are counted the first time they show up in the tree, always in the same
+// order so the same one gets the bytes on every run
+fn own_sizes( node : &Node, cfg : &Config, dedup : &mut Dedup, owners : &mut Owners ) -> Sizes {
+    let sizes = own_sizes_counted( node, cfg, dedup );
+    if cfg.owner_flag {
+        owners.add( node.uid, sizes );
     }
+    sizes
 }
 
-fn path_from_dentry( entry : Result<fs::DirEntry, io::Error> ) -> Option<std::path::PathBuf> {
-    match entry {
-        Ok(entry) => {
-            Some( entry.path() )
-        },
-        Err(err)  => {
-            eprintln!( "Couldn't read entry ({:?})", err.kind() );
-            None
+fn own_sizes_counted( node : &Node, cfg : &Config, dedup : &mut Dedup ) -> Sizes {
+    if !is_counted( node, cfg ) {
+        return Sizes { partial: node.partial, ..Sizes::default() };
+    }
+    // fast symlinks keep their target in the inode, they would all look like holes
+    let is_file = node.file_type == Some( FileType::File );
+    let sizes = Sizes {
+        apparent : node.apparent,
+        usage    : node.usage,
+        holes    : if is_file { node.apparent.saturating_sub( node.usage ) } else { 0 },
+        slack    : if is_file { node.usage.saturating_sub( node.apparent ) } else { 0 },
+        inodes   : 1,
+        partial  : node.partial,
+        matched  : !node.is_dir,
+    };
+    match node.link {
+        Some( id ) if !cfg.links_flag && !dedup.seen.insert( id ) => {
+            dedup.saved += sizes.size( cfg );
+            Sizes { matched: true, ..Sizes::default() }
         },
+        _ => sizes,
     }
 }
 
-fn print_io_error( path: &Path, err: io::Error ) {
-    eprintln!( "Couldn't read {} ({:?})", file_name_from_path( path ), err.kind() )
-}
-
 impl<'a> Entry<'a> {
-    fn new( path: &Path, cfg : &'a Config, depth : u8 ) -> Entry<'a> {
-        let name = file_name_from_path( path );
-
-        // recursively create directory tree of entries up to depth
-        let depth = if cfg.depth_flag { depth - 1 } else { 1 };
-
-        let entries = if path.is_dir() && ( !cfg.depth_flag || depth > 0 ) {
-            let mut aggr_bytes = 0;
-            if let Some( dir_list ) = try_read_dir( path ) {
-                let mut vec : Vec<Entry> = Vec::with_capacity( dir_list.size_hint().0 );
-                for entry in dir_list {
-                    if let Some( path ) = path_from_dentry( entry ) {
-                        let entry_name = &file_name_from_path(&path);
-
-                        // argument filters
-                        if cfg.exclude.iter().any( |p| entry_name == p ){ continue }
-                        if cfg.hiddn_flag && entry_name.starts_with("."){ continue }
-                        if cfg.no_dir_flg && path.is_dir()              { continue }
-
-                        let entry = Entry::new( &path.as_path(), cfg, depth );
-                        if cfg.aggr > 0 && entry.bytes < cfg.aggr {
-                            aggr_bytes += entry.bytes;
-                        } else {
-                            vec.push( entry );
-                        }
-                    }
+    // build the tree to display out of the scanned one, applying filters, depth and aggregation
+    // path is where the node is, rel the same path relative to the scan root. owners gets
+    // what each user has below it with --by-owner
+    fn prune( node : &Node, path : &Path, rel : &Path, cfg : &'a Config, level : u8, dedup : &mut Dedup,
+              owners : &mut Owners ) -> Entry<'a> {
+        let mut sizes = own_sizes( node, cfg, dedup, owners );
+        let children  = node.children.iter().flatten().map( |c| ( c, rel.join( &c.name ) ) )
+                                                      .filter( |( c, rel )| !is_excluded( c, rel, cfg ) );
+
+        // caches are shown collapsed, with what's in them added up
+        let entries = if node.children.is_some() && !node.cache && ( !cfg.depth_flag || level < cfg.depth ) {
+            let mut aggr_sizes = Sizes::default();
+            let mut vec : Vec<Entry> = Vec::new();
+            for ( child, rel ) in children {
+                let mut child_owners = Owners::default();
+                let entry = Entry::prune( child, &path.join( &child.name ), &rel, cfg, level + 1, dedup, &mut child_owners );
+                if is_emptied( child, &entry.sizes, cfg ) {
+                    continue;
                 }
-                vec.sort_unstable_by( |a, b| b.bytes.cmp( &a.bytes ) );
-                if aggr_bytes > 0 {
-                    vec.push( Entry {
-                        name: "<aggregated>".to_string(),
-                        bytes: aggr_bytes,
-                        color: None,
-                        last : true,
-                        entries: None,
-                    } );
-                }
-
-                let len = vec.len();
-                if len > 0 {
-                    vec[len-1].last = true;
+                sizes += entry.sizes;
+                owners.merge( child_owners );
+                if cfg.aggr > 0 && entry.bytes < cfg.aggr && entry.tag.is_none() {
+                    aggr_sizes += entry.sizes;
+                } else {
+                    vec.push( entry );
                 }
+            }
 
-                Some( vec )
-            } else { None }
-        } else { None };
+            vec.sort_unstable_by_key( |e| std::cmp::Reverse( e.bytes ) );
+            if aggr_sizes.bytes( cfg ) > 0 {
+                vec.push( Entry::pseudo( OsString::from( "<aggregated>" ), aggr_sizes, cfg ) );
+            }
 
-        // calculate sizes
-        let bytes = if let Some(ref entries) = entries {
-            let mut total = try_bytes_from_path( path, cfg.usage_flag );
-            for entry in entries {
-                total += entry.bytes;
+            Some( vec )
+        } else {
+            for ( child, mut rel ) in children {
+                let mut child_owners = Owners::default();
+                let child_sizes = total_sizes( child, &mut rel, cfg, dedup, &mut child_owners );
+                if !is_emptied( child, &child_sizes, cfg ) {
+                    sizes += child_sizes;
+                    owners.merge( child_owners );
+                }
             }
-            total
+            None
+        };
+
+        // owners go first, also for directories too deep to show what's in them
+        let mut entries = if cfg.owner_flag && node.is_dir {
+            let mut vec = Entry::owner_entries( owners, cfg );
+            vec.extend( entries.into_iter().flatten() );
+            Some( vec )
         } else {
-            get_bytes( path, cfg.usage_flag )
+            entries
         };
+        if let Some( last ) = entries.as_mut().and_then( |vec : &mut Vec<Entry>| vec.last_mut() ) {
+            last.last = true;
+        }
 
         // calculate color
-        let color = if !cfg.ascii_flag {color_from_path(path, &cfg.color_dict)} else {None};
+        let color = if !cfg.ascii_flag {color_from_node(node, path, &cfg.color_dict)} else {None};
+
+        Entry { name: node.name.clone(), bytes: sizes.bytes( cfg ), sizes, color, last: false,
+                entries, tag: node.tag.or( sizes.partial.then_some( "[incomplete]" ) ),
+                target: node.target.clone(), leaf: node.file_type == Some( FileType::File ) }
+    }
+
+    // entries that add up several others, like <aggregated>
+    fn pseudo( name : OsString, sizes : Sizes, cfg : &Config ) -> Entry<'a> {
+        Entry {
+            name,
+            bytes: sizes.bytes( cfg ),
+            sizes,
+            color: None,
+            last : false,
+            entries: None,
+            tag: None,
+            target: None,
+            leaf: false,
+        }
+    }
 
-        Entry { name, bytes, color, last: false, entries }
+    // one <user NAME> entry for each owner of something in a directory, largest first
+    fn owner_entries( owners : &Owners, cfg : &Config ) -> Vec<Entry<'a>> {
+        let mut vec : Vec<Entry> = owners.0.iter().map( |( uid, sizes )| {
+            Entry::pseudo( OsString::from( format!( "<user {}>", cfg.user_names.name( *uid ) ) ), *sizes, cfg )
+        } ).filter( |e| e.bytes > 0 ).collect();
+        vec.sort_unstable_by( |a, b| b.bytes.cmp( &a.bytes ).then_with( || a.name.cmp( &b.name ) ) );
+        vec
     }
 
-    fn print_entries( &self, open_parents : Vec<bool>, parent_vals : Vec<u64>,
-                      bytes_flag : bool, ascii_flag : bool,
+    // mostly empty sparse files, or mostly unused disk blocks. Directory totals are left
+    // alone, a few such files would flag every directory above them
+    fn waste_flag( &self ) -> Option<&'static str> {
+        let Sizes { apparent, usage, holes, slack, .. } = self.sizes;
+        if !self.leaf {
+            None
+        } else if holes > 0 && holes * 2 >= apparent {
+            Some( "[sparse]" )
+        } else if slack > 0 && slack * 2 >= usage {
+            Some( "[slack]" )
+        } else {
+            None
+        }
+    }
+
+    // one size column, or file size and disk usage with --both. Inode count with --inodes,
+    // holes and slack with --sparse
+    fn fmt_size_columns( &self, cfg : &Config ) -> String {
+        let bound    = self.fmt_bound( cfg );
+        let fmt_size = |bytes| format!( "{}{}", bound, fmt_size_str( bytes, cfg.bytes_flag ) );
+        let mut columns = Vec::new();
+        if cfg.inodes_flag {
+            columns.push( format!( "{}{}", bound, self.sizes.inodes ) );
+        }
+        if cfg.both_flag {
+            columns.push( fmt_size( self.sizes.apparent ) );
+            columns.push( fmt_size( self.sizes.usage ) );
+        } else if !cfg.inodes_flag {
+            columns.push( fmt_size( self.bytes ) );
+        }
+        if cfg.sparse_flag {
+            let fmt_waste = |bytes| if bytes > 0 { fmt_size( bytes ) } else { String::new() };
+            columns.push( fmt_waste( self.sizes.holes ) );
+            columns.push( fmt_waste( self.sizes.slack ) );
+        }
+        columns.iter().map( |c| format!( "{:>13}", c ) ).collect::<Vec<_>>().join( " " )
+    }
+
+    // sizes of partially scanned directories are only lower bounds
+    fn fmt_bound( &self, cfg : &Config ) -> &'static str {
+        match ( self.sizes.partial, cfg.ascii_flag ) {
+            ( false, _     ) => "",
+            ( true , false ) => "≥ ",
+            ( true , true  ) => ">= ",
+        }
+    }
+
+    fn print_entries( &self, open_parents : Vec<bool>, parent_vals : Vec<u64>, cfg : &Config,
                       max_bytes : u64, bar_width : usize, tree_name_width : usize ) {
         if let Some(ref entries) = self.entries {
             for entry in entries {
@@ -337,10 +764,20 @@ impl<'a> Entry<'a> {
                 let tree_width = (open_parents.len() + 1) * 3; // 3 chars per tree branch
                 if tree_name_width >= tree_width {
                     let name_width  = tree_name_width - tree_width;
-                    let length = UnicodeWidthStr::width(entry.name.as_str());
+                    let mut label = match entry.tag {
+                        Some( tag ) => format!( "{} {}", fmt_os_str( &entry.name ), tag ),
+                        None        => fmt_os_str( &entry.name ),
+                    };
+                    if let Some( ref target ) = entry.target {
+                        label = format!( "{} -> {}", label, fmt_os_str( target.as_os_str() ) );
+                    }
+                    if let Some( flag ) = entry.waste_flag().filter( |_| cfg.sparse_flag ) {
+                        label = format!( "{} {}", label, flag );
+                    }
+                    let length = UnicodeWidthStr::width(label.as_str());
 
                     // truncate Unicode string to name_width
-                    let graphemes = UnicodeSegmentation::graphemes( entry.name.as_str(), true );
+                    let graphemes = UnicodeSegmentation::graphemes( label.as_str(), true );
                     let mut i   = 0;
                     let mut vec = Vec::new();
                     for cluster in graphemes {
@@ -354,7 +791,7 @@ impl<'a> Entry<'a> {
                     vec.iter().for_each( |cluster| name.push_str( cluster ) );
 
                     // surround name by ANSII color escape sequences
-                    if let Some( ref col_str ) = entry.color {
+                    if let Some( col_str ) = entry.color {
                         name.insert( 0, 'm' );
                         name.insert( 0, 0o33 as char );
                         name.insert( 1, '[' );
@@ -376,12 +813,12 @@ impl<'a> Entry<'a> {
                     else            { print!( "├─ " ); op.push( false ); }
 
                     // print it
-                    println!( "{} {} {:>13}",
+                    println!( "{} {} {}",
                               name,
-                              fmt_bar( &bytes, max_bytes, bar_width, ascii_flag ),
-                              fmt_size_str( entry.bytes, bytes_flag ) );
-                    if let Some(_) = entry.entries {
-                        entry.print_entries( op, bytes, bytes_flag, ascii_flag,
+                              fmt_bar( &bytes, max_bytes, bar_width, cfg.ascii_flag ),
+                              entry.fmt_size_columns( cfg ) );
+                    if entry.entries.is_some() {
+                        entry.print_entries( op, bytes, cfg,
                                              max_bytes, bar_width, tree_name_width );
                     }
                 }
@@ -389,21 +826,14 @@ impl<'a> Entry<'a> {
         }
     }
 
-    fn print( &self, bytes_flag : bool, ascii_flag : bool ) {
+    fn print( &self, cfg : &Config, saved : u64 ) {
 
         // calculate plot widths
-        let mut twidth = DEF_WIDTH;
-        let size = terminal_size();
-        if let Some( ( Width(w), Height(_h) ) ) = size {
-            twidth = w;
-        } else {
-            // FIXME: doesn't seem to work when piping
-            // eprintln!("Unable to get terminal size");
-        }
-        let size_width      = 15;
-        let var_width       = (twidth - size_width) as usize;
+        let twidth          = term_width();
+        let size_width      = 1 + 14 * cfg.size_columns() as u16;
+        let var_width       = twidth.saturating_sub( size_width ) as usize;
         let tree_name_width = 25.max(var_width * 25 / 100);
-        let bar_width = var_width - tree_name_width;
+        let bar_width = var_width.saturating_sub( tree_name_width ).max( 12 ); // narrow terminals wrap
 
         // initalize
         let     open_parents : Vec<bool> = Vec::new();
@@ -415,13 +845,33 @@ impl<'a> Entry<'a> {
         parent_vals.push( self.bytes );
 
         // print
-        println!( "[ {} {} ]", self.name, fmt_size_str( self.bytes, bytes_flag ) );
-        self.print_entries( open_parents, parent_vals, bytes_flag, ascii_flag,
+        let bound      = self.fmt_bound( cfg );
+        let fmt_size   = |bytes| format!( "{}{}", bound, fmt_size_str( bytes, cfg.bytes_flag ) );
+        let mut header = fmt_size( self.bytes );
+        if cfg.both_flag {
+            header = format!( "{} apparent, {} on disk",
+                              fmt_size( self.sizes.apparent ), fmt_size( self.sizes.usage ) );
+        }
+        if cfg.inodes_flag {
+            let count = format!( "{}{} inodes", bound, self.sizes.inodes );
+            header = if cfg.both_flag { format!( "{}, {}", count, header ) } else { count };
+        }
+        if cfg.sparse_flag {
+            header = format!( "{}, {} in holes, {} slack",
+                              header, fmt_size( self.sizes.holes ), fmt_size( self.sizes.slack ) );
+        }
+        if saved > 0 {
+            println!( "[ {} {}, {} in {} counted once ]", fmt_os_str( &self.name ), header,
+                      fmt_size_str( saved, cfg.bytes_flag ), if cfg.follow_flag { "links" } else { "hard links" } );
+        } else {
+            println!( "[ {} {} ]", fmt_os_str( &self.name ), header );
+        }
+        self.print_entries( open_parents, parent_vals, cfg,
                             max_bytes, bar_width, tree_name_width );
     }
 }
 
-fn fmt_bar( bytes : &Vec<u64>, max_bytes : u64, width : usize, ascii_flag : bool ) -> String {
+fn fmt_bar( bytes : &[u64], max_bytes : u64, width : usize, ascii_flag : bool ) -> String {
     let width = width as u64 - 2 - 5; // not including bars and percentage
 
     let mut str = String::with_capacity( width as usize );
@@ -464,6 +914,90 @@ fn fmt_bar( bytes : &Vec<u64>, max_bytes : u64, width : usize, ascii_flag : bool
     format!( "{}│ {:3}%", str, result )
 }
 
+// letters as for find -type, links are not followed: a link is l even with -L
+fn parse_types( str : &str ) -> Option<Vec<FileType>> {
+    str.split( ',' ).map( |letter| match letter {
+        "f" => Some( FileType::File ),
+        "d" => Some( FileType::Dir ),
+        "l" => Some( FileType::Symlink ),
+        "p" => Some( FileType::Fifo ),
+        "s" => Some( FileType::Socket ),
+        "b" => Some( FileType::BlockDevice ),
+        "c" => Some( FileType::CharDevice ),
+        _   => None,
+    } ).collect()
+}
+
+// a number with an optional unit: B (default), K, M, G or T, in powers of 1024
+fn parse_size( str : &str ) -> Option<u64> {
+    if !Regex::new(r"^\d+\D?$").unwrap().is_match( str ) {
+        return None;
+    }
+
+    let unit = str.matches(char::is_alphabetic).next().unwrap_or("B");
+    let num : Vec<&str> = str.matches(char::is_numeric).collect();
+    let num : u64       = num.concat().parse().ok()?;
+
+    let factor = match unit {
+        "b" | "B" => 1024u64.pow(0),
+        "k" | "K" => 1024u64.pow(1),
+        "m" | "M" => 1024u64.pow(2),
+        "g" | "G" => 1024u64.pow(3),
+        "t" | "T" => 1024u64.pow(4),
+        _         => 1024u64.pow(0),
+    };
+    num.checked_mul( factor )
+}
+
+// a number with an optional unit: ms, s (default), m, h, d or w
+fn parse_duration( str : &str ) -> Option<Duration> {
+    let caps = Regex::new( r"^(\d+(?:\.\d+)?)(ms|s|m|h|d|w)?$" ).unwrap().captures( str )?;
+    let num : f64 = caps[1].parse().ok()?;
+    let secs = match caps.get(2).map_or( "s", |unit| unit.as_str() ) {
+        "ms" => num / 1000.0,
+        "m"  => num * 60.0,
+        "h"  => num * 60.0 * 60.0,
+        "d"  => num * 60.0 * 60.0 * 24.0,
+        "w"  => num * 60.0 * 60.0 * 24.0 * 7.0,
+        _    => num,
+    };
+    Duration::try_from_secs_f64( secs ).ok()   // too long to count
+}
+
+// a time as seconds since the epoch: a duration before now, see parse_duration, or a
+// UTC date as YYYY-MM-DD with an optional HH:MM[:SS]
+fn parse_time( str : &str, now : SystemTime ) -> Option<i64> {
+    if let Some( ago ) = parse_duration( str ) {
+        let now = now.duration_since( UNIX_EPOCH ).ok()?;
+        return Some( now.saturating_sub( ago ).as_secs() as i64 );
+    }
+
+    let caps = Regex::new( r"^(\d{4})-(\d{2})-(\d{2})(?:[ T](\d{2}):(\d{2})(?::(\d{2}))?)?$" ).unwrap().captures( str )?;
+    let num  = |i| caps.get( i ).map_or( 0, |m| m.as_str().parse::<i64>().unwrap() );
+    let ( year, month, day, hour, min, sec ) = ( num(1), num(2), num(3), num(4), num(5), num(6) );
+    let leap = ( year % 4 == 0 && year % 100 != 0 ) || year % 400 == 0;
+    let month_days = match month { 2 if leap => 29, 2 => 28, 4 | 6 | 9 | 11 => 30, _ => 31 };
+    if !( 1..=12 ).contains( &month ) || !( 1..=month_days ).contains( &day ) || hour > 23 || min > 59 || sec > 59 {
+        return None;
+    }
+
+    // days since the epoch, see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era  = year.div_euclid( 400 );
+    let yoe  = year - era * 400;
+    let doy  = ( 153 * ( ( month + 9 ) % 12 ) + 2 ) / 5 + day - 1;
+    let doe  = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some( days * 86400 + hour * 3600 + min * 60 + sec )
+}
+
+fn term_width() -> u16 {
+    match terminal_size() {
+        Some( ( Width(w), Height(_h) ) ) => w,
+        None => DEF_WIDTH,  // FIXME: doesn't seem to work when piping
+    }
+}
+
 fn fmt_size_str( bytes : u64, flag : bool ) -> String {
     let b = bytes as f32;
     if      bytes < 1024 || flag   { format!( "{:.2} B"  , bytes                    ) }
@@ -473,76 +1007,72 @@ fn fmt_size_str( bytes : u64, flag : bool ) -> String {
     else                           { format!( "{:.2} TiB", b/(1024u64.pow(4) as f32)) }
 }
 
-fn get_bytes( path: &Path, usage_flag : bool ) -> u64 {
-    if path.is_dir() {
-        let mut bytes : u64 = try_bytes_from_path( path, usage_flag );
-        if let Some(dir_list) = try_read_dir( path ) {
-            for entry in dir_list {
-                if let Some(path) = path_from_dentry( entry ) {
-                    bytes += get_bytes( &path, usage_flag );
-                }
+// names are shown as UTF-8, with invalid bytes and control characters escaped as \xNN
+// and \ itself as \\, so the output can't be mistaken for another name or move the cursor
+pub(crate) fn fmt_os_str( name : &OsStr ) -> String {
+    let mut str = String::with_capacity( name.len() );
+    let escape  = |str : &mut String, bytes : &[u8]| bytes.iter().for_each( |b| str.push_str( &format!( "\\x{:02X}", b ) ) );
+    for chunk in name.as_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\'                => str.push_str( "\\\\" ),
+                c if c.is_control() => escape( &mut str, c.encode_utf8( &mut [0; 4] ).as_bytes() ),
+                c                   => str.push( c ),
             }
         }
-        bytes
-    } else {
-        try_bytes_from_path( path, usage_flag )
+        escape( &mut str, chunk.invalid() );
     }
+    str
 }
 
-fn color_from_path<'a>( path : &Path, color_dict : &'a HashMap<String, String> ) -> Option<&'a str> {
-    if try_is_symlink( path ) {
-        let path_link = path.read_link();
-        if path_link.is_ok() {
-            if path_link.unwrap().exists() {
-                if let Some(col) = color_dict.get(&"ln".to_string()) {
-                    return Some(&col);
-                }
+// file type and mode come from the scan, only links are checked for their target
+fn color_from_node<'a>( node : &Node, path : &Path, color_dict : &'a HashMap<String, String> ) -> Option<&'a str> {
+    if node.file_type == Some( FileType::Symlink ) {
+        if path.exists() {
+            if let Some(col) = color_dict.get(&"ln".to_string()) {
+                return Some(col);
             }
         }
         if let Some( col ) = color_dict.get( &"or".to_string() )  {
-            return Some( &col );
+            return Some( col );
         }
     }
-    let metadata = path.symlink_metadata();
-    if metadata.is_ok() {
-        #[cfg(any(target_os = "freebsd", target_os = "linux"))]
-        let mode = metadata.unwrap().st_mode();
-        #[cfg(target_os = "macos")]
-        let mode = metadata.unwrap().mode();
-        if path.is_dir() {
+    if node.file_type.is_some() {
+        let mode = node.mode;
+        if node.is_dir {
             if mode & 0o002 != 0 {  // dir other writable
                 if let Some( col ) = color_dict.get( &"ow".to_string() ) {
-                    return Some( &col );
+                    return Some( col );
                 }
             }
             if let Some( col ) = color_dict.get( &"di".to_string() ) {
-                return Some( &col );
+                return Some( col );
             }
         }
         if mode & 0o111 != 0 {  // executable
             if let Some( col ) = color_dict.get( &"ex".to_string() ) {
-                return Some( &col );
+                return Some( col );
             }
         }
     }
     if let Some( ext_str ) = path.extension() {
-        for ( key , _ ) in color_dict {
+        for ( key, col ) in color_dict {
             if &key[..2] != "*." { continue }
             let k = key.trim_start_matches( "*." );
             if ext_str == k {
-                return Some( &color_dict.get( key ).unwrap() );
+                return Some( col );
             }
         }
     }
-    if path.is_file() {
+    if node.file_type == Some( FileType::File ) {
         if let Some( col ) = color_dict.get( &"fi".to_string() ) {
-            return Some( &col );
+            return Some( col );
         }
         else { return None }
     }
     // we are assuming it can only be a 'bd','cd'. can also be 'pi','so' or 'no'
     if let Some( col ) = color_dict.get( &"bd".to_string() ) {
-        return Some( &col );
+        return Some( col );
     }
     None
 }
@@ -557,7 +1087,7 @@ fn create_color_dict() -> HashMap<String, String> {
     let colors  = env_str.split(':');
     let mut color_dict = HashMap::with_capacity( colors.size_hint().0 );
     for entry in colors {
-        if entry.len() == 0 { break; }
+        if entry.is_empty() { break; }
 
         let     line = entry.replace( "\"", "" );
         let mut line = line.split('=');
@@ -569,38 +1099,967 @@ fn create_color_dict() -> HashMap<String, String> {
     color_dict
 }
 
-pub fn run( cfg: &Config ) {
-    let entry = if cfg.paths.len() == 1 {
-        Entry::new( cfg.paths[0].as_path(), &cfg, cfg.depth + 1 )
-    } else {
-        let mut bytes = 0;
-        let mut entries : Vec<Entry> = Vec::with_capacity( cfg.paths.len() );
+// scan all paths, then prune them into the tree to display.
+// Also returns the bytes not counted because of repeated hard links
+fn tree<'a>( cfg: &'a Config, state : &ScanState ) -> ( Entry<'a>, u64 ) {
+    let nodes = scan::scan( &cfg.paths, cfg, state );
 
-        for path in &cfg.paths {
-            let e = Entry::new( path.as_path(), &cfg, cfg.depth + 1 );
-            bytes += e.bytes;
-            entries.push( e );
-        }
-        entries.sort_unstable_by( |a, b| b.bytes.cmp( &a.bytes ) );
-        let len = entries.len();
-        if len > 0 {
-            entries[len-1].last = true;
-        }
-        Entry {
-            name    : "<collection>".to_string(),
-            bytes,
-            color   : None,
-            last    : false,
-            entries : Some(entries)
-        }
-    };
+    let mut dedup = Dedup { seen: HashSet::new(), saved: 0 };
+    let mut entries : Vec<Entry> = nodes.iter().zip( &cfg.paths )
+        .map( |( node, path )| Entry::prune( node, path, Path::new( "" ), cfg, 0, &mut dedup, &mut Owners::default() ) )
+        .collect();
+
+    if entries.len() == 1 {
+        return ( entries.remove( 0 ), dedup.saved );
+    }
+
+    let mut sizes = Sizes::default();
+    entries.iter().for_each( |e| sizes += e.sizes );
+    entries.sort_unstable_by_key( |e| std::cmp::Reverse( e.bytes ) );
+    let len = entries.len();
+    if len > 0 {
+        entries[len-1].last = true;
+    }
+    ( Entry {
+        name    : OsString::from( "<collection>" ),
+        bytes   : sizes.bytes( cfg ),
+        sizes,
+        color   : None,
+        last    : false,
+        entries : Some(entries),
+        tag     : None,
+        target  : None,
+        leaf    : false,
+    }, dedup.saved )
+}
+
+fn thread_pool( threads : usize ) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new().num_threads( threads ).stack_size( STACK_SIZE ).build()
+        .expect( "couldn't create thread pool" )
+}
 
-    entry.print( cfg.bytes_flag, cfg.ascii_flag );
+// returns the exit status: 1 if part of the tree couldn't be read, like du, and
+// 130 if the scan was interrupted before it was done. Running out of --max-time or --max-entries is not
+// an error
+pub fn run( cfg: &Config ) -> i32 {
+    let pool = thread_pool( cfg.threads );
+    let state = ScanState { max_time: cfg.max_time, max_entries: cfg.max_entries, ..Default::default() };
+    let ( entry, saved ) = state.progress.show_while( cfg.progress, cfg.bytes_flag,
+                                                      || pool.install( || tree( cfg, &state ) ) );
+
+    entry.print( cfg, saved );
+
+    let mut status = if state.errors.is_empty() { 0 } else { 1 };
+    state.errors.report( cfg.errors );
+    if entry.sizes.partial {
+        let why = if scan::interrupted() { "interrupted" } else { "limit reached" };
+        eprintln!( "Scan {}, sizes shown with {} are lower bounds", why, entry.fmt_bound( cfg ).trim() );
+        if scan::interrupted() {
+            status = 130;
+        }
+    }
+    status
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use errors::ErrorKind;
+    use std::fs;
+
+    fn test_config( args : &[&str] ) -> Config {
+        let mut args : Vec<String> = args.iter().map( |a| a.to_string() ).collect();
+        args.insert( 0, "dutree".to_string() );
+        match Config::from_args( args ) {
+            XOk(cfg) => cfg,
+            _        => panic!( "invalid test arguments" ),
+        }
+    }
+
+    fn flatten( entry : &Entry, depth : usize, out : &mut Vec<(usize, OsString, u64)> ) {
+        out.push( ( depth, entry.name.clone(), entry.bytes ) );
+        if let Some( ref entries ) = entry.entries {
+            entries.iter().for_each( |e| flatten( e, depth + 1, out ) );
+        }
+    }
+
+    fn scan_with_threads( cfg : &Config, threads : usize ) -> Vec<(usize, OsString, u64)> {
+        let pool = thread_pool( threads );
+        let mut out = Vec::new();
+        flatten( &pool.install( || tree( cfg, &ScanState::default() ) ).0, 0, &mut out );
+        out
+    }
+
+    // fresh scratch directory, unique per test
+    fn temp_dir( name : &str ) -> PathBuf {
+        let dir = env::temp_dir().join( format!( "dutree-{}-{}", name, std::process::id() ) );
+        let _ = fs::remove_dir_all( &dir );
+        fs::create_dir_all( &dir ).unwrap();
+        dir
+    }
+
+    #[test]
+    fn hard_links_counted_once() {
+        let dir = temp_dir( "links" );
+        fs::create_dir( dir.join( "a" ) ).unwrap();
+        fs::create_dir( dir.join( "b" ) ).unwrap();
+        fs::write( dir.join( "a/file" ), vec![0u8; 1000] ).unwrap();
+        fs::hard_link( dir.join( "a/file" ), dir.join( "a/link" ) ).unwrap();
+        fs::hard_link( dir.join( "a/file" ), dir.join( "b/link" ) ).unwrap();
+        let dir_str = dir.to_str().unwrap();
+
+        let cfg = test_config( &[ "-d1", dir_str ] );
+        let ( entry, saved ) = tree( &cfg, &ScanState::default() );
+        assert_eq!( saved, 2000 );
+
+        // the file is counted in exactly one of the two directories
+        let dir_bytes = |e : &Entry| fs::metadata( dir.join( &e.name ) ).unwrap().len();
+        let children  = entry.entries.as_ref().unwrap();
+        assert_eq!( children.iter().map( |e| e.bytes - dir_bytes( e ) ).sum::<u64>(), 1000 );
+
+        // across several roots too
+        let a = dir.join( "a" );
+        let b = dir.join( "b" );
+        let cfg = test_config( &[ a.to_str().unwrap(), b.to_str().unwrap() ] );
+        assert_eq!( tree( &cfg, &ScanState::default() ).1, 2000 );
+
+        let cfg = test_config( &[ "--count-links", dir_str ] );
+        assert_eq!( tree( &cfg, &ScanState::default() ).1, 0 );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn follow_symlinks_without_looping() {
+        let ( dir, outside ) = ( temp_dir( "symlinks" ), temp_dir( "symlinks-outside" ) );
+        fs::create_dir_all( dir.join( "real" ) ).unwrap();
+        fs::create_dir_all( dir.join( "sub" ) ).unwrap();
+        fs::write( dir.join( "real/file" ), vec![0u8; 1000] ).unwrap();
+        fs::write( outside.join( "file" ), vec![0u8; 2000] ).unwrap();
+        std::os::unix::fs::symlink( "real", dir.join( "link" ) ).unwrap();
+        std::os::unix::fs::symlink( "../real", dir.join( "sub/link" ) ).unwrap();
+        std::os::unix::fs::symlink( "..", dir.join( "real/loop" ) ).unwrap();
+        std::os::unix::fs::symlink( &outside, dir.join( "out" ) ).unwrap();
+        std::os::unix::fs::symlink( &outside, dir.join( "sub/out" ) ).unwrap();
+        std::os::unix::fs::symlink( "real/file", dir.join( "filelink" ) ).unwrap();
+        std::os::unix::fs::symlink( "nonexistent", dir.join( "orphan" ) ).unwrap();
+        let file_bytes = |e : &Entry| e.entries.iter().flatten()
+            .filter( |e| e.name == "file" ).map( |e| e.bytes ).sum::<u64>();
+        fn find<'a, 'b>( entry : &'b Entry<'a>, name : &str ) -> &'b Entry<'a> {
+            entry.entries.iter().flatten().find( |e| e.name == name ).unwrap()
+        }
+
+        // directories are counted once, through their own path or else the first link to them,
+        // and so are files
+        let cfg = test_config( &[ "-L", "-a0", "-d3", dir.to_str().unwrap() ] );
+        let ( entry, saved ) = tree( &cfg, &ScanState::default() );
+        assert_eq!( saved, 1000 );
+        let out = find( &entry, "out" );
+        assert_eq!( out.target, Some( outside.clone() ) );
+        assert_eq!( file_bytes( out ), 2000 );
+        let sub = find( &entry, "sub" );
+        for link in &[ find( &entry, "link" ), find( sub, "link" ), find( sub, "out" ) ] {
+            assert_eq!( ( link.target.as_ref(), link.entries.is_none() ), ( None, true ) );
+        }
+        // nothing to follow
+        assert_eq!( find( &entry, "orphan" ).target, None );
+
+        let cfg   = test_config( &[ dir.to_str().unwrap() ] );
+        let plain = tree( &cfg, &ScanState::default() ).0;
+        let link_bytes = |name| fs::symlink_metadata( dir.join( name ) ).unwrap().len();
+        assert_eq!( entry.bytes, plain.bytes - link_bytes( "out" ) - link_bytes( "filelink" ) +
+                                 fs::metadata( &outside ).unwrap().len() + 2000 );
+
+        let cfg = test_config( &[ "--follow-cmdline", dir.join( "link" ).to_str().unwrap() ] );
+        assert_eq!( file_bytes( &tree( &cfg, &ScanState::default() ).0 ), 1000 );
+
+        fs::remove_dir_all( &dir ).unwrap();
+        fs::remove_dir_all( &outside ).unwrap();
+    }
+
+    // links are only followed once all roots are scanned, whatever the order of the roots
+    #[test]
+    fn follow_symlinks_across_roots() {
+        let dir = temp_dir( "symlinks-roots" );
+        fs::create_dir_all( dir.join( "A" ) ).unwrap();
+        fs::create_dir_all( dir.join( "B/zzz" ) ).unwrap();
+        fs::write( dir.join( "B/zzz/file" ), vec![0u8; 1000] ).unwrap();
+        std::os::unix::fs::symlink( "../B/zzz", dir.join( "A/L" ) ).unwrap();
+        let ( a, b ) = ( dir.join( "A" ), dir.join( "B" ) );
+
+        for roots in &[ [ &b, &a ], [ &a, &b ] ] {
+            let cfg = test_config( &[ "-L", "-a0", roots[0].to_str().unwrap(), roots[1].to_str().unwrap() ] );
+            for threads in &[ 1, 2, 8 ] {
+                let rows = scan_with_threads( &cfg, *threads );
+                let bytes = |name : &str| rows.iter().find( |r| r.1 == name ).unwrap().2;
+                assert!( bytes( "zzz" ) >= 1000, "{:?}", rows );
+                assert!( bytes( "L" ) < 1000, "{:?}", rows );
+            }
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    // a directory under two of the roots is counted under the first one given, however
+    // the threads get to it
+    #[test]
+    fn overlapping_roots() {
+        let dir = temp_dir( "overlapping-roots" );
+        for d in 0..20 {
+            fs::create_dir_all( dir.join( format!( "share/doc/{}", d ) ) ).unwrap();
+            fs::write( dir.join( format!( "share/doc/{}/file", d ) ), vec![0u8; 1000] ).unwrap();
+        }
+        // the other threads are up by the time share is read
+        for f in 0..2000 {
+            fs::write( dir.join( format!( "share/{}", f ) ), b"" ).unwrap();
+        }
+        let ( share, doc ) = ( dir.join( "share" ), dir.join( "share/doc" ) );
+
+        for roots in &[ [ &share, &doc ], [ &doc, &share ] ] {
+            let cfg = test_config( &[ "-L", "-a0", roots[0].to_str().unwrap(), roots[1].to_str().unwrap() ] );
+            let first = scan_with_threads( &cfg, 1 );
+            // share/doc is one level further down than the doc root
+            let counted = if roots[0] == &share { 2 } else { 1 };
+            let docs : Vec<(usize, u64)> = first.iter().filter( |r| r.1 == "doc" ).map( |r| ( r.0, r.2 ) ).collect();
+            assert_eq!( docs.len(), 2 );
+            assert!( docs.iter().all( |&( depth, bytes )| ( bytes >= 20000 ) == ( depth == counted ) ), "{:?}", docs );
+            for threads in &[ 2, 4, 8, 8, 8 ] {
+                assert_eq!( scan_with_threads( &cfg, *threads ), first );
+            }
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn errors_collected_with_paths() {
+        let dir = temp_dir( "errors" );
+        fs::create_dir( dir.join( "real" ) ).unwrap();
+        std::os::unix::fs::symlink( "..", dir.join( "real/loop" ) ).unwrap();
+        let missing = dir.join( "missing" );
+        fs::create_dir( &missing ).unwrap();
+
+        let cfg = test_config( &[ "-L", dir.to_str().unwrap(), missing.to_str().unwrap() ] );
+        fs::remove_dir( &missing ).unwrap();   // gone by the time it is scanned
+        let state = ScanState::default();
+        tree( &cfg, &state );
+        let kinds : Vec<(PathBuf, ErrorKind)> = state.errors.sorted().into_iter().map( |e| ( e.path, e.kind ) ).collect();
+        assert_eq!( kinds, vec![ ( missing, ErrorKind::Os( libc::ENOENT ) ),
+                                 ( dir.join( "real/loop" ), ErrorKind::SymlinkLoop ) ] );
+        assert_eq!( ErrorKind::Os( libc::EMFILE ).to_string(), "Too many open files" );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn filters_apply_below_depth() {
+        let dir = temp_dir( "filters" );
+        fs::create_dir_all( dir.join( "sub/deep" ) ).unwrap();
+        fs::write( dir.join( "keep" ), vec![0u8; 100] ).unwrap();
+        fs::write( dir.join( "sub/skip" ), vec![0u8; 1000] ).unwrap();
+        fs::write( dir.join( "sub/deep/skip" ), vec![0u8; 1000] ).unwrap();
+        fs::write( dir.join( "sub/deep/keep" ), vec![0u8; 10] ).unwrap();
+        let dirs_bytes : u64 = [ "", "sub", "sub/deep" ].iter()
+            .map( |d| fs::metadata( dir.join( d ) ).unwrap().len() ).sum();
+
+        for depth in &[ "-d0", "-d1", "-d3" ] {
+            let cfg = test_config( &[ depth, "-x", "skip", dir.to_str().unwrap() ] );
+            assert_eq!( tree( &cfg, &ScanState::default() ).0.bytes, dirs_bytes + 110 );
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn exclude_globs() {
+        let dir = temp_dir( "globs" );
+        fs::create_dir_all( dir.join( "sub/cache-x" ) ).unwrap();
+        for file in &[ "a.o", "b.c", "keep.tmp", "sub/c.o", "sub/d.tmp", "sub/cache-x/f" ] {
+            fs::write( dir.join( file ), b"" ).unwrap();
+        }
+
+        let cases = [ ( "*.o", 7 ), ( "sub/*.tmp", 8 ), ( "**/cache-*", 7 ), ( "[ab].*", 7 ), ( "sub", 4 ), ( "*", 1 ) ];
+        for ( pattern, inodes ) in &cases {
+            for depth in &[ "-d0", "-d3" ] {
+                let cfg = test_config( &[ "--inodes", depth, "-x", pattern, dir.to_str().unwrap() ] );
+                assert_eq!( tree( &cfg, &ScanState::default() ).0.bytes, *inodes, "{} {}", pattern, depth );
+            }
+        }
+
+        let args = [ "dutree", "-x", "[", dir.to_str().unwrap() ].iter().map( |a| a.to_string() ).collect();
+        assert!( matches!( Config::from_args( args ), XErr(_) ) );
+
+        // excluded directories are not scanned, so nothing in them is reported
+        std::os::unix::fs::symlink( "..", dir.join( "sub/up" ) ).unwrap();
+        for ( args, errors ) in &[ ( &[ "-L" ][..], 1 ), ( &[ "-L", "-x", "sub" ], 0 ), ( &[ "-L", "-f" ], 0 ),
+                                   ( &[ "-L", "--exclude-regex", "^sub$" ], 0 ) ] {
+            let mut args = args.to_vec();
+            args.push( dir.to_str().unwrap() );
+            let state = ScanState::default();
+            tree( &test_config( &args ), &state );
+            assert_eq!( state.errors.sorted().len(), *errors, "{:?}", args );
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn files_only() {
+        let dir = temp_dir( "files-only" );
+        fs::create_dir_all( dir.join( "sub" ) ).unwrap();
+        fs::write( dir.join( "file" ), b"x" ).unwrap();
+        std::os::unix::fs::symlink( "sub", dir.join( "dirlink" ) ).unwrap();
+        std::os::unix::fs::symlink( "file", dir.join( "filelink" ) ).unwrap();
+
+        let cfg = test_config( &[ "-f", "-a0", dir.to_str().unwrap() ] );
+        let entry = tree( &cfg, &ScanState::default() ).0;
+        let mut names : Vec<&OsString> = entry.entries.iter().flatten().map( |e| &e.name ).collect();
+        names.sort();
+        assert_eq!( names, [ "file", "filelink" ] );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn regex_filters() {
+        let dir = temp_dir( "regex" );
+        fs::create_dir_all( dir.join( "a/b" ) ).unwrap();
+        fs::create_dir_all( dir.join( "c" ) ).unwrap();
+        fs::create_dir_all( dir.join( "d" ) ).unwrap();
+        for file in &[ "a/b/x.log", "a/b/y.txt", "c/z.txt", "top.log" ] {
+            fs::write( dir.join( file ), b"" ).unwrap();
+        }
+
+        let cases : [ ( &[&str], u64 ); 3 ] = [ ( &[ "--include-regex", r"\.log$" ], 5 ),
+                                                ( &[ "--exclude-regex", "^a/" ], 6 ),
+                                                ( &[ "--include-regex", r"\.log$", "--exclude-regex", "b" ], 2 ) ];
+        for ( filters, inodes ) in &cases {
+            for depth in &[ "-d0", "-d3" ] {
+                let mut args = vec![ "--inodes", depth, dir.to_str().unwrap() ];
+                args.extend_from_slice( filters );
+                let cfg = test_config( &args );
+                assert_eq!( tree( &cfg, &ScanState::default() ).0.bytes, *inodes, "{:?} {}", filters, depth );
+            }
+        }
+
+        // directories holding matches are kept to show where they are
+        let cfg = test_config( &[ "-d3", "--include-regex", r"\.log$", dir.to_str().unwrap() ] );
+        let mut names = Vec::new();
+        flatten( &tree( &cfg, &ScanState::default() ).0, 0, &mut names );
+        let mut names : Vec<OsString> = names.into_iter().skip( 1 ).map( |( _, name, _ )| name ).collect();
+        names.sort();
+        assert_eq!( names, [ "a", "b", "top.log", "x.log" ] );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn gitignore_files() {
+        let dir = temp_dir( "gitignore" );
+        fs::create_dir_all( dir.join( ".git/info" ) ).unwrap();
+        fs::create_dir_all( dir.join( "target" ) ).unwrap();
+        fs::create_dir_all( dir.join( "src/gen" ) ).unwrap();
+        fs::write( dir.join( ".git/info/exclude" ), "*.log\n" ).unwrap();
+        fs::write( dir.join( ".gitignore" ), "target/\nbuild/\n" ).unwrap();
+        fs::write( dir.join( "src/gen/.gitignore" ), "*\n!keep.rs\n" ).unwrap();
+        for file in &[ "build", "target/a", "src/main.rs", "src/a.log", "src/gen/out.rs", "src/gen/keep.rs" ] {
+            fs::write( dir.join( file ), b"" ).unwrap();
+        }
+
+        let src = dir.join( "src" );
+        let cases = [ ( [ "--respect-gitignore", dir.to_str().unwrap() ], 7 ),
+                      ( [ "--only-ignored", dir.to_str().unwrap() ], 11 ),
+                      ( [ "--respect-gitignore", src.to_str().unwrap() ], 4 ) ];
+        for ( args, inodes ) in &cases {
+            for depth in &[ "-d0", "-d3" ] {
+                let mut args = args.to_vec();
+                args.extend_from_slice( &[ "--inodes", depth ] );
+                let cfg = test_config( &args );
+                assert_eq!( tree( &cfg, &ScanState::default() ).0.bytes, *inodes, "{:?}", args );
+            }
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn exclude_files() {
+        let dir = temp_dir( "dutreeignore" );
+        fs::create_dir_all( dir.join( "sub/build" ) ).unwrap();
+        fs::write( dir.join( ".dutreeignore" ), "*.log\nlist\n" ).unwrap();
+        fs::write( dir.join( "sub/.dutreeignore" ), "!keep.log\nbuild/\n" ).unwrap();
+        fs::write( dir.join( "list" ), "# sources\n\n*.rs\n.dutreeignore  \n" ).unwrap();
+        for file in &[ "a.log", "main.rs", "sub/keep.log", "sub/b.log", "sub/build/x" ] {
+            fs::write( dir.join( file ), b"" ).unwrap();
+        }
+
+        // the .dutreeignore files above a scan root don't apply
+        let ( list, sub ) = ( dir.join( "list" ), dir.join( "sub" ) );
+        let cases = [ ( vec![ dir.to_str().unwrap() ], 6 ),
+                      ( vec![ "--exclude-from", list.to_str().unwrap(), dir.to_str().unwrap() ], 3 ),
+                      ( vec![ sub.to_str().unwrap() ], 4 ) ];
+        for ( args, inodes ) in &cases {
+            let mut args = args.clone();
+            args.push( "--inodes" );
+            let cfg = test_config( &args );
+            assert_eq!( tree( &cfg, &ScanState::default() ).0.bytes, *inodes, "{:?}", args );
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn cache_dirs() {
+        let dir = temp_dir( "caches" );
+        fs::create_dir_all( dir.join( "tagged" ) ).unwrap();
+        fs::create_dir_all( dir.join( "fake" ) ).unwrap();
+        fs::write( dir.join( "tagged/CACHEDIR.TAG" ), "Signature: 8a477f597d28d172789f06886806bc55\n# a cache\n" ).unwrap();
+        fs::write( dir.join( "fake/CACHEDIR.TAG" ), "Signature: 8a477f597d28d172789f06886806bc5" ).unwrap();
+        fs::write( dir.join( "tagged/obj" ), b"" ).unwrap();
+        fs::write( dir.join( "file" ), b"" ).unwrap();
+
+        let path = dir.to_str().unwrap();
+        for ( args, inodes ) in &[ ( &[ path ][..], 7 ), ( &[ "--exclude-caches", path ], 4 ),
+                                   ( &[ "--exclude-caches=collapse", path ], 7 ) ] {
+            let mut args = args.to_vec();
+            args.extend_from_slice( &[ "--inodes", "-d2" ] );
+            let mut entries = Vec::new();
+            flatten( &tree( &test_config( &args ), &ScanState::default() ).0, 0, &mut entries );
+            assert_eq!( entries[0].2, *inodes, "{:?}", args );
+            let tagged = entries.iter().filter( |e| e.1 == "tagged" ).map( |e| e.2 ).collect::<Vec<_>>();
+            assert_eq!( tagged, match args[0] { "--exclude-caches" => vec![], _ => vec![ 3 ] } );
+            assert_eq!( entries.iter().any( |e| e.1 == "obj" ), args[0] == path );
+        }
+
+        // tagged roots are still scanned
+        let cfg = test_config( &[ "--inodes", "--exclude-caches", dir.join( "tagged" ).to_str().unwrap() ] );
+        assert_eq!( tree( &cfg, &ScanState::default() ).0.bytes, 3 );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn apparent_size_and_usage() {
+        let dir = temp_dir( "both" );
+        fs::File::create( dir.join( "sparse" ) ).unwrap().set_len( 1024 * 1024 ).unwrap();
+        let path = dir.join( "sparse" );
+        let usage = std::os::unix::fs::MetadataExt::blocks( &fs::metadata( &path ).unwrap() ) * 512;
+
+        let cfg = test_config( &[ "--both", path.to_str().unwrap() ] );
+        let entry = tree( &cfg, &ScanState::default() ).0;
+        assert_eq!( entry.bytes, 1024 * 1024 );
+        assert_eq!( ( entry.sizes.apparent, entry.sizes.usage ), ( 1024 * 1024, usage ) );
+
+        let cfg = test_config( &[ "--both", "-u", path.to_str().unwrap() ] );
+        let entry = tree( &cfg, &ScanState::default() ).0;
+        assert_eq!( entry.bytes, usage );
+        assert_eq!( ( entry.sizes.apparent, entry.sizes.usage ), ( 1024 * 1024, usage ) );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn sparse_holes_and_slack() {
+        let dir = temp_dir( "sparse" );
+        fs::File::create( dir.join( "sparse" ) ).unwrap().set_len( 1024 * 1024 ).unwrap();
+        fs::create_dir( dir.join( "sub" ) ).unwrap();
+        fs::File::create( dir.join( "sub/sparse" ) ).unwrap().set_len( 1024 * 1024 ).unwrap();
+        fs::write( dir.join( "tiny" ), b"x" ).unwrap();
+        std::os::unix::fs::symlink( "tiny", dir.join( "link" ) ).unwrap();
+        let blocks = |name| std::os::unix::fs::MetadataExt::blocks( &fs::metadata( dir.join( name ) ).unwrap() ) * 512;
+        let ( sparse_usage, tiny_usage ) = ( blocks( "sparse" ), blocks( "tiny" ) );
+
+        let cfg = test_config( &[ "--sparse", "-a0", dir.to_str().unwrap() ] );
+        let entry = tree( &cfg, &ScanState::default() ).0;
+        assert_eq!( entry.sizes.holes, 2 * ( 1024 * 1024 - sparse_usage ) );
+        assert_eq!( entry.sizes.slack, tiny_usage.saturating_sub( 1 ) );
+
+        let flag = |name : &str| entry.entries.as_ref().unwrap().iter()
+                                      .find( |e| e.name == name ).unwrap().waste_flag();
+        assert_eq!( flag( "sparse" ), Some( "[sparse]" ) );
+        assert_eq!( flag( "sub" ), None );
+        assert_eq!( flag( "link" ), None );
+        assert_eq!( entry.waste_flag(), None );
+        if tiny_usage > 1 {
+            assert_eq!( flag( "tiny" ), Some( "[slack]" ) );
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn count_inodes() {
+        let dir = temp_dir( "inodes" );
+        fs::create_dir( dir.join( "sub" ) ).unwrap();
+        for name in &[ "a", "b", "sub/c" ] {
+            fs::write( dir.join( name ), vec![ 0; 4096 ] ).unwrap();
+        }
+        fs::hard_link( dir.join( "a" ), dir.join( "sub/a" ) ).unwrap();
+        std::os::unix::fs::symlink( "a", dir.join( "link" ) ).unwrap();
+
+        let cfg = test_config( &[ "--inodes", dir.to_str().unwrap() ] );
+        let state = ScanState::default();
+        assert_eq!( tree( &cfg, &state ).0.bytes, 6 );
+        assert_eq!( state.progress.entries(), 7 );  // the scan sees both hard links
+
+        let cfg = test_config( &[ "--inodes", "-l", dir.to_str().unwrap() ] );
+        let entry = tree( &cfg, &ScanState::default() ).0;
+        assert_eq!( entry.bytes, 7 );
+        let sub = entry.entries.as_ref().unwrap().iter().find( |e| e.name == "sub" ).unwrap();
+        assert_eq!( sub.bytes, 3 );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn partial_scans_are_lower_bounds() {
+        let dir = temp_dir( "partial" );
+        fs::create_dir_all( dir.join( "sub/deep" ) ).unwrap();
+        fs::write( dir.join( "file" ), vec![0u8; 100] ).unwrap();
+
+        let cfg = test_config( &[ "-A", dir.to_str().unwrap() ] );
+        let mut node = scan::scan( &cfg.paths, &cfg, &ScanState::default() ).remove( 0 );
+        for child in node.children.iter_mut().flatten().filter( |c| c.name == "sub" ) {
+            child.partial = true;
+        }
+        let mut dedup = Dedup { seen: HashSet::new(), saved: 0 };
+        let entry = Entry::prune( &node, &dir, Path::new( "" ), &cfg, 0, &mut dedup, &mut Owners::default() );
+        assert!( entry.sizes.partial );
+        assert_eq!( entry.fmt_bound( &cfg ), ">= " );
+
+        let tag = |name : &str| entry.entries.iter().flatten().find( |e| e.name == name ).unwrap().tag;
+        assert_eq!( tag( "sub" ), Some( "[incomplete]" ) );
+        assert_eq!( tag( "file" ), None );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    // the interrupt flag is global and would stop the scans of the other tests too, so the
+    // test runs itself again in a process of its own
+    #[test]
+    fn interrupted_scan() {
+        if std::env::var_os( "DUTREE_TEST_INTERRUPT" ).is_none() {
+            let out = std::process::Command::new( std::env::current_exe().unwrap() )
+                .args( [ "--exact", "tests::interrupted_scan" ] ).env( "DUTREE_TEST_INTERRUPT", "1" )
+                .output().unwrap();
+            assert!( out.status.success(), "{}", String::from_utf8_lossy( &out.stdout ) );
+            return;
+        }
+
+        let dir = temp_dir( "interrupt" );
+        fs::create_dir_all( dir.join( "sub" ) ).unwrap();
+        fs::write( dir.join( "sub/file" ), vec![0u8; 100] ).unwrap();
+
+        let cfg = test_config( &[ "-A", "--progress", "never", dir.to_str().unwrap() ] );
+        scan::interrupt();
+        let entry = tree( &cfg, &ScanState::default() ).0;
+        assert!( entry.sizes.partial );
+        // sub wasn't scanned, it is still shown
+        let sub = entry.entries.iter().flatten().find( |e| e.name == "sub" ).unwrap();
+        assert_eq!( ( sub.tag, sub.bytes ), ( Some( "[incomplete]" ), 0 ) );
+        assert_eq!( run( &cfg ), 130 );
+
+        // nothing left to scan when it came, the tree is complete
+        let cfg = test_config( &[ "-A", "--progress", "never", dir.join( "sub/file" ).to_str().unwrap() ] );
+        assert_eq!( run( &cfg ), 0 );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn entry_budget() {
+        let dir = temp_dir( "budget" );
+        for d in 0..4 {
+            fs::create_dir( dir.join( d.to_string() ) ).unwrap();
+            for f in 0..50 {
+                fs::write( dir.join( format!( "{}/{}", d, f ) ), b"x" ).unwrap();
+            }
+        }
+
+        let cfg = test_config( &[ "--inodes", "--max-entries", "20", dir.to_str().unwrap() ] );
+        let state = ScanState { max_entries: cfg.max_entries, ..Default::default() };
+        let entry = tree( &cfg, &state ).0;
+        assert!( entry.sizes.partial );
+        assert!( entry.bytes < 205 );
+
+        // the ones not scanned are still there, as placeholders
+        let mut names : Vec<&OsStr> = entry.entries.iter().flatten().map( |e| e.name.as_os_str() ).collect();
+        names.sort();
+        assert_eq!( names, [ "0", "1", "2", "3" ] );
+        assert!( entry.entries.iter().flatten().all( |e| e.tag == Some( "[incomplete]" ) ) );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    type NodeRow = ( usize, OsString, u64, u64, Option<FileType>, u32, Option<PathBuf> );
+
+    fn flatten_node( node : &Node, depth : usize, out : &mut Vec<NodeRow> ) {
+        out.push( ( depth, node.name.clone(), node.apparent, node.usage, node.file_type, node.mode,
+                    node.target.clone() ) );
+        node.children.iter().flatten().for_each( |c| flatten_node( c, depth + 1, out ) );
+    }
+
+    #[cfg(target_os = "linux")]
+    fn scan_rows<B : backend::Backend>( backend : &B, dir : &Path, cfg : &Config ) -> ( Vec<NodeRow>, Vec<errors::ScanError> ) {
+        let state = ScanState::default();
+        let mut rows = Vec::new();
+        flatten_node( &scan::scan_with( backend, &[ dir.to_path_buf() ], cfg, &state )[0], 0, &mut rows );
+        ( rows, state.errors.sorted() )
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn backends_agree() {
+        let dir = temp_dir( "backends" );
+        fs::create_dir_all( dir.join( "real/sub" ) ).unwrap();
+        fs::write( dir.join( "real/sub/file" ), vec![0u8; 5000] ).unwrap();
+        std::os::unix::fs::symlink( "real", dir.join( "link" ) ).unwrap();
+        std::os::unix::fs::symlink( "..", dir.join( "real/loop" ) ).unwrap();
+        std::os::unix::fs::symlink( "nowhere", dir.join( "orphan" ) ).unwrap();
+
+        for flags in &[ "-d1", "-L" ] {
+            let cfg = test_config( &[ flags, dir.to_str().unwrap() ] );
+            let rows = scan_rows( &backend::portable::Std, &dir, &cfg );
+            assert_eq!( scan_rows( &backend::linux::Linux::new(), &dir, &cfg ), rows );
+            #[cfg(feature = "io-uring")]
+            if let Some( uring ) = backend::uring::Uring::new() {
+                assert_eq!( scan_rows( &uring, &dir, &cfg ), rows );
+            }
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    // the portable backend, with every directory named mnt on another filesystem
+    struct OtherDev;
+
+    impl backend::Backend for OtherDev {
+        type Dir = PathBuf;
+
+        fn stat( &self, at : backend::At<PathBuf>, follow : bool ) -> std::io::Result<backend::Stat> {
+            let mut stat = backend::portable::Std.stat( at, follow )?;
+            if matches!( at, backend::At::Entry( _, name ) if name == "mnt" ) {
+                stat.dev += 1;
+            }
+            Ok( stat )
+        }
+        fn open_dir( &self, at : backend::At<PathBuf>, follow : bool ) -> std::io::Result<PathBuf> {
+            backend::portable::Std.open_dir( at, follow )
+        }
+        fn read_dir( &self, dir : &PathBuf ) -> std::io::Result<Vec<std::io::Result<backend::DirEntry>>> {
+            backend::portable::Std.read_dir( dir )
+        }
+        fn read_link( &self, at : backend::At<PathBuf> ) -> std::io::Result<PathBuf> {
+            backend::portable::Std.read_link( at )
+        }
+        fn read_file( &self, at : backend::At<PathBuf>, limit : u64 ) -> std::io::Result<Vec<u8>> {
+            backend::portable::Std.read_file( at, limit )
+        }
+    }
+
+    #[test]
+    fn one_file_system() {
+        let dir = temp_dir( "xdev" );
+        fs::create_dir_all( dir.join( "mnt/sub" ) ).unwrap();
+        fs::create_dir_all( dir.join( "local" ) ).unwrap();
+        fs::write( dir.join( "mnt/sub/file" ), b"x" ).unwrap();
+        fs::write( dir.join( "local/file" ), b"x" ).unwrap();
+
+        for ( flags, mounted ) in &[ ( &[][..], 2 ), ( &[ "--one-file-system" ][..], 0 ) ] {
+            let mut args = flags.to_vec();
+            args.push( dir.to_str().unwrap() );
+            let cfg  = test_config( &args );
+            let node = scan::scan_with( &OtherDev, &cfg.paths, &cfg, &ScanState::default() ).remove( 0 );
+            let child = |name : &str| node.children.iter().flatten().find( |c| c.name == name ).unwrap();
+            let mut rows = Vec::new();
+            flatten_node( child( "mnt" ), 0, &mut rows );
+            assert_eq!( rows.len() - 1, *mounted, "{:?}", flags );
+            assert_eq!( child( "mnt" ).tag, flags.first().map( |_| "[mount point]" ) );
+            assert!( child( "local" ).children.as_ref().is_some_and( |c| c.len() == 1 ) );
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn deeper_than_path_max() {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+        use std::io::Write;
+
+        let dir  = temp_dir( "deep" );
+        let name = std::ffi::CString::new( "d".repeat( 200 ) ).unwrap();
+        let mut fd = fs::File::open( &dir ).map( OwnedFd::from ).unwrap();
+        for _ in 0..3000 {
+            unsafe {
+                assert_eq!( libc::mkdirat( fd.as_raw_fd(), name.as_ptr(), 0o755 ), 0 );
+                let sub = libc::openat( fd.as_raw_fd(), name.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY );
+                assert!( sub >= 0 );
+                fd = OwnedFd::from_raw_fd( sub );
+            }
+        }
+
+        // at the bottom, files the scanner has to read too
+        let write = |at : &OwnedFd, file : &str, text : &str| unsafe {
+            let file = std::ffi::CString::new( file ).unwrap();
+            let out  = libc::openat( at.as_raw_fd(), file.as_ptr(), libc::O_WRONLY | libc::O_CREAT, 0o644 );
+            assert!( out >= 0 );
+            fs::File::from_raw_fd( out ).write_all( text.as_bytes() ).unwrap();
+        };
+        let cache = std::ffi::CString::new( "cache" ).unwrap();
+        let cache = unsafe {
+            assert_eq!( libc::mkdirat( fd.as_raw_fd(), cache.as_ptr(), 0o755 ), 0 );
+            OwnedFd::from_raw_fd( libc::openat( fd.as_raw_fd(), cache.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY ) )
+        };
+        write( &cache, "CACHEDIR.TAG", "Signature: 8a477f597d28d172789f06886806bc55" );
+        write( &fd, ".dutreeignore", "skipped\n" );
+        write( &fd, "skipped", "" );
+
+        let cfg   = test_config( &[ "--inodes", "-d1", "--exclude-caches", dir.to_str().unwrap() ] );
+        let state = ScanState::default();
+        let bytes = thread_pool( 4 ).install( || tree( &cfg, &state ).0.bytes );
+        assert_eq!( bytes, 3002 );
+        assert!( state.errors.is_empty() );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    // far more levels than recursing once per level would fit in the stack, or than
+    // keeping each one open would fit in the file descriptor limit. That is lowered for
+    // the whole process, so the test runs itself again in a process of its own
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn thirty_thousand_levels_deep() {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+        if std::env::var_os( "DUTREE_TEST_FD_LIMIT" ).is_none() {
+            let out = std::process::Command::new( std::env::current_exe().unwrap() )
+                .args( [ "--exact", "tests::thirty_thousand_levels_deep" ] ).env( "DUTREE_TEST_FD_LIMIT", "1" )
+                .output().unwrap();
+            assert!( out.status.success(), "{}", String::from_utf8_lossy( &out.stdout ) );
+            return;
+        }
+
+        // a few files next to each directory, that are scanned while it is open
+        let dir   = temp_dir( "levels" );
+        let name  = std::ffi::CString::new( "d" ).unwrap();
+        let files : Vec<std::ffi::CString> = ( 0..3 ).map( |f| std::ffi::CString::new( f.to_string() ).unwrap() ).collect();
+        let mut fd = fs::File::open( &dir ).map( OwnedFd::from ).unwrap();
+        for _ in 0..30000 {
+            unsafe {
+                for file in &files {
+                    let file = libc::openat( fd.as_raw_fd(), file.as_ptr(), libc::O_WRONLY | libc::O_CREAT, 0o644 );
+                    assert!( file >= 0 );
+                    libc::close( file );
+                }
+                assert_eq!( libc::mkdirat( fd.as_raw_fd(), name.as_ptr(), 0o755 ), 0 );
+                let sub = libc::openat( fd.as_raw_fd(), name.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY );
+                assert!( sub >= 0 );
+                fd = OwnedFd::from_raw_fd( sub );
+            }
+        }
+        drop( fd );
+
+        let limit = libc::rlimit { rlim_cur: 256, rlim_max: 256 };
+        assert_eq!( unsafe { libc::setrlimit( libc::RLIMIT_NOFILE, &limit ) }, 0 );
+        let cfg   = test_config( &[ "--inodes", "-d1", dir.to_str().unwrap() ] );
+        let state = ScanState::default();
+        let bytes = thread_pool( 4 ).install( || tree( &cfg, &state ).0.bytes );
+        assert!( state.errors.is_empty(), "{:?}", state.errors.sorted().first().map( |e| e.kind ) );
+        assert_eq!( bytes, 1 + 4 * 30000 );
+
+        // std would keep every level open, the chain is taken apart from the top instead
+        let ( top, next ) = ( dir.join( "d" ), dir.join( "next" ) );
+        for file in 0..3 {
+            fs::remove_file( dir.join( file.to_string() ) ).unwrap();
+        }
+        while top.exists() {
+            for file in 0..3 {
+                let _ = fs::remove_file( top.join( file.to_string() ) );
+            }
+            let _ = fs::rename( top.join( "d" ), &next );
+            fs::remove_dir( &top ).unwrap();
+            let _ = fs::rename( &next, &top );
+        }
+        fs::remove_dir( &dir ).unwrap();
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!( parse_duration( "30" ),    Some( Duration::from_secs( 30 ) ) );
+        assert_eq!( parse_duration( "500ms" ), Some( Duration::from_millis( 500 ) ) );
+        assert_eq!( parse_duration( "1.5m" ),  Some( Duration::from_secs( 90 ) ) );
+        assert_eq!( parse_duration( "2d" ),    Some( Duration::from_secs( 2 * 86400 ) ) );
+        assert_eq!( parse_duration( "5 m" ),   None );
+        assert_eq!( parse_duration( "99999999999999999999999w" ), None );
+    }
+
+    #[test]
+    fn size_filters() {
+        assert_eq!( parse_size( "100" ), Some( 100 ) );
+        assert_eq!( parse_size( "2K" ),  Some( 2048 ) );
+        assert_eq!( parse_size( "1m" ),  Some( 1024 * 1024 ) );
+        assert_eq!( parse_size( "1.5M" ), None );
+
+        let dir = temp_dir( "sizes" );
+        fs::create_dir_all( dir.join( "sub" ) ).unwrap();
+        for ( file, size ) in &[ ( "small", 10 ), ( "sub/medium", 1000 ), ( "sub/large", 100000 ) ] {
+            fs::write( dir.join( file ), vec![0u8; *size] ).unwrap();
+        }
+
+        // directories only add their own size if it's in range too
+        let dirs_bytes = |min, max| [ "", "sub" ].iter().map( |d| fs::metadata( dir.join( d ) ).unwrap().len() )
+                                                  .filter( |size| ( min..=max ).contains( size ) ).sum::<u64>();
+        let cases = [ ( &[ "--min-size", "1K" ][..], 100000 + dirs_bytes( 1024, u64::MAX ) ),
+                      ( &[ "--max-size", "1K" ], 1010 + dirs_bytes( 0, 1024 ) ),
+                      ( &[ "--min-size", "100", "--max-size", "1M" ], 101000 + dirs_bytes( 100, 1024 * 1024 ) ) ];
+        for ( filters, bytes ) in &cases {
+            for depth in &[ "-d0", "-d2" ] {
+                let mut args = vec![ depth, dir.to_str().unwrap() ];
+                args.extend_from_slice( filters );
+                let cfg = test_config( &args );
+                assert_eq!( tree( &cfg, &ScanState::default() ).0.bytes, *bytes, "{:?}", filters );
+            }
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn type_filter() {
+        let dir = temp_dir( "types" );
+        fs::create_dir_all( dir.join( "sub" ) ).unwrap();
+        fs::write( dir.join( "file" ), b"" ).unwrap();
+        fs::write( dir.join( "sub/file" ), b"" ).unwrap();
+        std::os::unix::fs::symlink( "file", dir.join( "sub/link" ) ).unwrap();
+        let _socket = std::os::unix::net::UnixListener::bind( dir.join( "socket" ) ).unwrap();
+
+        // directories that don't match are kept, without counting themselves
+        for ( types, inodes, shown ) in &[ ( "l", 1, 3 ), ( "d", 2, 2 ), ( "f,s", 3, 5 ), ( "l,f", 3, 5 ) ] {
+            let cfg = test_config( &[ "--inodes", "-d2", "--type", types, dir.to_str().unwrap() ] );
+            let mut entries = Vec::new();
+            flatten( &tree( &cfg, &ScanState::default() ).0, 0, &mut entries );
+            assert_eq!( ( entries[0].2, entries.len() ), ( *inodes, *shown ), "{}", types );
+        }
+
+        let args = [ "dutree", "--type", "f,x", dir.to_str().unwrap() ].iter().map( |a| a.to_string() ).collect();
+        assert!( matches!( Config::from_args( args ), XErr(_) ) );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn parse_times() {
+        let now = UNIX_EPOCH + Duration::from_secs( 1_000_000 );
+        assert_eq!( parse_time( "1d", now ),               Some( 1_000_000 - 86400 ) );
+        assert_eq!( parse_time( "1970-01-01", now ),       Some( 0 ) );
+        assert_eq!( parse_time( "2000-03-01", now ),       Some( 951868800 ) );
+        assert_eq!( parse_time( "2024-02-29 12:30", now ), Some( 1709209800 ) );
+        assert_eq!( parse_time( "2024-02-29T12:30:00", now ), Some( 1709209800 ) );
+        assert_eq!( parse_time( "2024-13-01", now ),       None );
+        assert_eq!( parse_time( "2024-02-31", now ),       None );
+        assert_eq!( parse_time( "2023-02-29", now ),       None );
+        assert_eq!( parse_time( "2024-04-31", now ),       None );
+        assert_eq!( parse_time( "1900-02-29", now ),       None );
+        assert_eq!( parse_time( "yesterday", now ),        None );
+    }
+
+    #[test]
+    fn time_filters_keep_structure() {
+        let dir = temp_dir( "times" );
+        fs::create_dir_all( dir.join( "sub" ) ).unwrap();
+        let at = |secs| UNIX_EPOCH + Duration::from_secs( secs );
+        for ( file, size, mtime, atime ) in &[ ( "old", 1000, 1577836800, 1577836800 ),     // 2020-01-01
+                                                ( "sub/old", 10, 1590969600, 1590969600 ),  // 2020-06-01
+                                                ( "new", 100, 0, 0 ) ] {
+            let f = fs::File::create( dir.join( file ) ).unwrap();
+            f.set_len( *size ).unwrap();
+            if *mtime > 0 {
+                f.set_times( fs::FileTimes::new().set_modified( at( *mtime ) ).set_accessed( at( *atime ) ) ).unwrap();
+            }
+        }
+        let dirs_bytes : u64 = [ "", "sub" ].iter().map( |d| fs::metadata( dir.join( d ) ).unwrap().len() ).sum();
+
+        let cases = [ ( "--older-than", "30d", 1010 ), ( "--older-than", "2020-03-01", 1000 ),
+                      ( "--newer-than", "1d", 100 + dirs_bytes ), ( "--accessed-before", "2021-01-01", 1010 ) ];
+        for ( option, time, bytes ) in &cases {
+            let cfg = test_config( &[ "-d1", option, time, dir.to_str().unwrap() ] );
+            let mut entries = Vec::new();
+            flatten( &tree( &cfg, &ScanState::default() ).0, 0, &mut entries );
+            assert_eq!( entries[0].2, *bytes, "{} {}", option, time );
+            assert!( entries.iter().any( |( _, name, _ )| name == "sub" ) );
+        }
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn owner_filters() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = temp_dir( "owners" );
+        fs::create_dir_all( dir.join( "sub" ) ).unwrap();
+        fs::write( dir.join( "sub/file" ), vec![0u8; 1000] ).unwrap();
+        let meta = fs::metadata( &dir ).unwrap();
+        let path = dir.to_str().unwrap();
+        let all  = tree( &test_config( &[ path ] ), &ScanState::default() ).0.bytes;
+
+        let ( uid, gid ) = ( meta.uid().to_string(), meta.gid().to_string() );
+        let other = ( meta.uid() + 1 ).to_string();
+        // files of someone else are left out, directories are kept
+        for ( args, bytes, shown ) in &[ ( [ "--user", &uid ], all, 3 ), ( [ "--group", &gid ], all, 3 ),
+                                         ( [ "--user", &other ], 0, 2 ) ] {
+            let cfg = test_config( &[ args[0], args[1], path ] );
+            let mut entries = Vec::new();
+            flatten( &tree( &cfg, &ScanState::default() ).0, 0, &mut entries );
+            assert_eq!( ( entries[0].2, entries.len() ), ( *bytes, *shown ), "{:?}", args );
+        }
+
+        // owners come first in each directory, also past the depth limit
+        let cfg = test_config( &[ "--by-owner", "-d1", path ] );
+        let mut entries = Vec::new();
+        flatten( &tree( &cfg, &ScanState::default() ).0, 0, &mut entries );
+        let owner = OsString::from( format!( "<user {}>", Names::users().name( meta.uid() ) ) );
+        assert_eq!( entries[1], ( 1, owner.clone(), all ) );
+        assert_eq!( entries[3], ( 2, owner, all - meta.len() ) );
+
+        let passwd = dir.join( "passwd" );
+        fs::write( &passwd, "# comment\nroot:x:0:0::/root:/bin/sh\ntoor:x:0:0::/root:/bin/sh\nbad:x:y\n" ).unwrap();
+        let names = Names::read( &passwd );
+        assert_eq!( ( names.name( 0 ), names.name( 42 ) ), ( "root".to_string(), "42".to_string() ) );
+        assert_eq!( ( names.id( "toor" ), names.id( "7" ), names.id( "bad" ) ), ( Some( 0 ), Some( 7 ), None ) );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
+
+    #[test]
+    fn parallel_scan_matches_serial() {
+        let cfg = test_config( &[ "-d3", "-A", "test", "src" ] );
+        let serial = scan_with_threads( &cfg, 1 );
+        for _ in 0..4 {
+            assert_eq!( serial, scan_with_threads( &cfg, 8 ) );
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_names() {
+        let dir  = temp_dir( "names" );
+        let name = OsStr::from_bytes( b"caf\xe9 \xff\xfe.txt" );
+        fs::write( dir.join( name ), b"x" ).unwrap();
+        fs::write( dir.join( "café" ), b"x" ).unwrap();
+
+        let cfg = test_config( &[ dir.to_str().unwrap() ] );
+        let entry = tree( &cfg, &ScanState::default() ).0;
+        let mut names : Vec<&OsStr> = entry.entries.iter().flatten().map( |e| e.name.as_os_str() ).collect();
+        names.sort();
+        assert_eq!( names, vec![ OsStr::new( "café" ), name ] );
+        assert_eq!( fmt_os_str( name ), "caf\\xE9 \\xFF\\xFE.txt" );
+        assert_eq!( fmt_os_str( OsStr::new( "café" ) ), "café" );
+        assert_eq!( fmt_os_str( OsStr::new( "caf\\xE9" ) ), "caf\\\\xE9" );
+        assert_eq!( fmt_os_str( OsStr::new( "a\x1b[31mb\nc\u{85}" ) ), "a\\x1B[31mb\\x0Ac\\xC2\\x85" );
+
+        fs::remove_dir_all( &dir ).unwrap();
+    }
 
     #[test]
     fn parse_ls_colors() {
@@ -609,9 +2068,12 @@ mod tests {
         dict.insert( "li".to_string(), "linkcod".to_string() );
         dict.insert( "*.mp3".to_string(), "mp3code".to_string() );
         dict.insert( "*.tar".to_string(), "tarcode".to_string() );
-        assert_eq!( "dircode", color_from_path( Path::new(".")       , &dict ).unwrap() );
-        assert_eq!( "mp3code", color_from_path( Path::new("test.mp3"), &dict ).unwrap() );
-        assert_eq!( "tarcode", color_from_path( Path::new("test.tar"), &dict ).unwrap() );
+        let cfg   = test_config( &[ "src" ] );
+        let color = |path| color_from_node( &scan::scan( &[ PathBuf::from(path) ], &cfg, &ScanState::default() )[0],
+                                            Path::new(path), &dict );
+        assert_eq!( "dircode", color( "src"      ).unwrap() );
+        assert_eq!( "mp3code", color( "test.mp3" ).unwrap() );
+        assert_eq!( "tarcode", color( "test.tar" ).unwrap() );
     }
 
     /*
@@ -12,10 +12,59 @@
 //!     -a, --aggr [N[KMG]] aggregate smaller than N B/KiB/MiB/GiB (def 1M)
 //!     -s, --summary       equivalent to -da, or -d1 -a1M
 //!     -u, --usage         report real disk usage instead of file size
+//!         --both          show file size and disk usage, sort by -u choice
+//!         --sparse        report sparse file holes and unused block slack
+//!         --inodes        count files, dirs and links instead of bytes; -a
+//!                         counts them too (def 1K)
 //!     -b, --bytes         print sizes in bytes
-//!     -x, --exclude NAME  exclude matching files or directories
+//!     -l, --count-links   count sizes many times if hard linked
+//!     -f, --files-only    skip directories for a fast local overview
+//!     -x, --exclude PATTERN
+//!                         exclude files or directories matching a glob
+//!         --exclude-from FILE
+//!                         exclude the globs listed in FILE, one per line
+//!         --exclude-regex REGEX
+//!                         exclude paths below the root matching REGEX
+//!         --include-regex REGEX
+//!                         only count files with paths matching REGEX
+//!         --respect-gitignore 
+//!                         exclude files ignored by .gitignore or .ignore files
+//!         --only-ignored  only count files ignored by .gitignore or .ignore
+//!                         files
+//!         --older-than WHEN
+//!                         only count files modified before WHEN, a time ago
+//!                         (e.g. 180d) or a UTC date (e.g. 2024-01-31)
+//!         --newer-than WHEN
+//!                         only count files modified after WHEN
+//!         --accessed-before WHEN
+//!                         only count files last accessed before WHEN
+//!         --min-size N[KMG]
+//!                         only count files of at least N B/KiB/MiB/GiB
+//!         --max-size N[KMG]
+//!                         only count files of at most N B/KiB/MiB/GiB
+//!         --type TYPES    only count entries of these types, comma separated: f
+//!                         file, d dir, l symlink, p fifo, s socket, b block or c
+//!                         char device
+//!         --user USER     only count files owned by USER, a name or a uid
+//!         --group GROUP   only count files of GROUP, a name or a gid
+//!         --by-owner      break down directories by owner
+//!         --exclude-caches [collapse]
+//!                         exclude directories tagged with CACHEDIR.TAG, or with
+//!                         =collapse show them without their contents
 //!     -H, --no-hidden     exclude hidden files
+//!         --one-file-system 
+//!                         skip directories on different file systems
+//!     -L, --follow-symlinks 
+//!                         follow all symbolic links
+//!         --follow-cmdline 
+//!                         follow symbolic links given as arguments
 //!     -A, --ascii         ASCII characters only, no colors
+//!     -j, --threads N     scan using N threads (def: number of CPUs)
+//!         --errors MODE   report unreadable paths: summary, full or quiet
+//!         --progress WHEN show scan progress on stderr: auto, always or never
+//!         --max-time DURATION
+//!                         stop scanning after DURATION (e.g. 500ms, 30s, 5m)
+//!         --max-entries N stop scanning after N files and directories
 //!     -h, --help          show help
 //!     -v, --version       print version number
 //! ```
@@ -47,6 +96,9 @@ fn main() {
     // handle SIGPIPE
     let _signal = unsafe { signal_hook::register(signal_hook::SIGPIPE, || process::exit(0)) };
 
+    // on Ctrl-C stop scanning, and print what was found so far
+    let _signal = unsafe { signal_hook::register(signal_hook::SIGINT, dutree::interrupt) };
+
     // Parse arguments
     let cfg = match Config::new() {
         XOk(cfg)  => cfg,
@@ -58,7 +110,7 @@ fn main() {
     };
 
     // Execution
-    dutree::run( &cfg );
+    process::exit( dutree::run( &cfg ) );
 }
 
 // License
@@ -0,0 +1,106 @@
+//!
+//! Scan progress
+//!
+//! Scanning threads count what they have seen so far. While the scan runs, a separate
+//! thread can redraw a status line on stderr with those counts
+//!
+
+use crate::fmt_os_str;
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const REFRESH : Duration = Duration::from_millis( 200 );
+
+pub struct Progress {
+    entries : AtomicU64,
+    bytes   : AtomicU64,
+    current : Mutex<PathBuf>,   // a directory read since the last redraw
+    wanted  : AtomicBool,       // the next directory read goes to current
+    start   : Instant,
+}
+
+impl Default for Progress {
+    fn default() -> Progress {
+        Progress { entries: AtomicU64::new( 0 ), bytes: AtomicU64::new( 0 ),
+                   current: Mutex::new( PathBuf::new() ), wanted: AtomicBool::new( true ), start: Instant::now() }
+    }
+}
+
+impl Progress {
+    // one more file, directory or link
+    pub fn add( &self, bytes : u64 ) {
+        self.entries.fetch_add( 1, Ordering::Relaxed );
+        self.bytes.fetch_add( bytes, Ordering::Relaxed );
+    }
+
+    // only informative, so the path is only built once per redraw
+    pub fn enter<F : FnOnce() -> PathBuf>( &self, dir : F ) {
+        if self.wanted.load( Ordering::Relaxed ) && self.wanted.swap( false, Ordering::Relaxed ) {
+            *self.current.lock().unwrap() = dir();
+        }
+    }
+
+    pub fn entries( &self ) -> u64 {
+        self.entries.load( Ordering::Relaxed )
+    }
+
+    pub fn elapsed( &self ) -> Duration {
+        self.start.elapsed()
+    }
+
+    // runs f, redrawing the progress line meanwhile if show is set. The line is
+    // cleared before returning
+    pub fn show_while<T, F : FnOnce() -> T>( &self, show : bool, bytes_flag : bool, f : F ) -> T {
+        if !show { return f() }
+
+        let done = AtomicBool::new( false );
+        thread::scope( |s| {
+            let drawer = s.spawn( || {
+                while !done.load( Ordering::Relaxed ) {
+                    self.draw( bytes_flag );
+                    thread::park_timeout( REFRESH );
+                }
+                eprint!( "\r\x1b[K" );
+                let _ = io::stderr().flush();
+            } );
+            let result = f();
+            done.store( true, Ordering::Relaxed );
+            drawer.thread().unpark();
+            drawer.join().unwrap();
+            result
+        } )
+    }
+
+    fn draw( &self, bytes_flag : bool ) {
+        let current = self.current.lock().unwrap().clone();
+        self.wanted.store( true, Ordering::Relaxed );
+        let line = format!( "{} entries, {}, {:.1}s  {}", self.entries(),
+                            crate::fmt_size_str( self.bytes.load( Ordering::Relaxed ), bytes_flag ),
+                            self.elapsed().as_secs_f32(), fmt_os_str( current.as_os_str() ) );
+        let width = crate::term_width() as usize - 1;
+        let line : String = line.chars().take( width ).collect();
+        eprint!( "\r\x1b[K{}", line );
+        let _ = io::stderr().flush();
+    }
+}
+
+// License
+//
+// This script is free software; you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This script is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this script; if not, write to the
+// Free Software Foundation, Inc., 59 Temple Place, Suite 330,
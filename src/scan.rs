@@ -0,0 +1,724 @@
+//!
+//! Filesystem traversal
+//!
+//! The whole tree below a path is read once and kept in memory, except for what the
+//! exclusions leave out. The filters that decide what counts, depth and aggregation
+//! are applied afterwards, when pruning it for display
+//!
+
+use crate::Config;
+use crate::errors::{Errors, ErrorKind};
+use crate::progress::Progress;
+use crate::backend::{self, At, Backend, DirEntry, FileType, Stat};
+use crate::gitignore::Ignores;
+
+use std::io;
+use std::mem;
+use std::cell::OnceCell;
+use std::collections::HashSet;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+pub struct Node {
+    pub name      : OsString,
+    pub apparent  : u64,                    // own sizes, children not included
+    pub usage     : u64,
+    pub is_dir    : bool,
+    pub link      : Option<(u64, u64)>,     // ( st_dev, st_ino ) of files with several hard links, all with -L
+    pub children  : Option<Vec<Node>>,      // None for files and unreadable directories
+    pub tag       : Option<&'static str>,
+    pub target    : Option<PathBuf>,        // followed symlinks
+    pub file_type : Option<FileType>,       // of the entry itself, links are not followed
+    pub mode      : u32,                    // permission bits
+    pub partial   : bool,                   // the scan stopped before listing all children
+    pub ignored   : bool,                   // by a .gitignore or .ignore file, only looked up if needed
+    pub excluded  : bool,                   // by a .dutreeignore file
+    pub cache     : bool,                   // tagged with CACHEDIR.TAG, only looked up with --exclude-caches
+    pub deferred  : Option<(u64, u64)>,     // ( st_dev, st_ino ) of the directory a link points to, see follow_deferred
+    pub mtime     : i64,
+    pub atime     : i64,
+    pub uid       : u32,
+    pub gid       : u32,
+}
+
+impl Node {
+    // placeholder for a directory that is not read, a mount point skipped by --one-file-system
+    // or one already counted through another path with -L
+    fn skipped_dir( name : OsString, tag : &'static str, file_type : Option<FileType>, mode : u32 ) -> Node {
+        Node { name, apparent: 0, usage: 0, is_dir: true, link: None, children: None,
+               tag: Some( tag ), target: None, file_type, mode, partial: false, ignored: false,
+               excluded: false, cache: false, deferred: None, mtime: 0, atime: 0, uid: 0, gid: 0 }
+    }
+
+    fn unreadable( name : OsString, file_type : Option<FileType> ) -> Node {
+        Node { name, apparent: 0, usage: 0, is_dir: false, link: None, children: None,
+               tag: None, target: None, file_type, mode: 0, partial: false, ignored: false, excluded: false,
+               cache: false, deferred: None, mtime: 0, atime: 0, uid: 0, gid: 0 }
+    }
+
+    // placeholder for an entry the scan stopped before, its sizes are lower bounds
+    fn unscanned( name : OsString, file_type : Option<FileType>, parents : &Parents ) -> Node {
+        let is_dir = file_type == Some( FileType::Dir );
+        let ( ignored, excluded ) = ( is_ignored( parents, &name, is_dir ), is_excluded( parents, &name, is_dir ) );
+        Node { is_dir, partial: true, ignored, excluded, ..Node::unreadable( name, file_type ) }
+    }
+}
+
+// shared by all scanning threads, and still there once the scan is over
+#[derive(Default)]
+pub struct ScanState {
+    pub errors      : Errors,
+    pub progress    : Progress,
+    pub max_time    : Option<Duration>,
+    pub max_entries : Option<u64>,
+    pub visited     : Mutex<HashSet<(u64, u64)>>,   // ( st_dev, st_ino ) of the directories scanned with -L
+}
+
+impl ScanState {
+    // entries not scanned yet are only placeholders. Threads already running can go a bit
+    // over the budget, they are not stopped halfway through an entry
+    pub fn stopped( &self ) -> bool {
+        interrupted() ||
+            self.max_entries.is_some_and( |max| self.progress.entries() >= max ) ||
+            self.max_time.is_some_and( |max| self.progress.elapsed() >= max )
+    }
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load( Ordering::Relaxed )
+}
+
+// set from the SIGINT handler, so it can't live in a ScanState
+static INTERRUPTED : AtomicBool = AtomicBool::new( false );
+
+// a second one gives up on printing anything. Runs in the signal handler, where
+// flushing stdout on the way out could wait forever for a lock the main thread holds
+pub fn interrupt() {
+    if INTERRUPTED.swap( true, Ordering::Relaxed ) {
+        unsafe { libc::_exit( 130 ) };
+    }
+}
+
+// what scan_node needs besides the entry itself, the same for all scan roots
+struct Walk<'a, B : Backend> {
+    backend  : &'a B,
+    cfg      : &'a Config,
+    state    : &'a ScanState,
+    deferred : Mutex<Vec<Deferred<B::Dir>>>,
+    claims   : Mutex<Vec<Claim>>,
+    stack    : Mutex<Stack<B::Dir>>,
+    wake     : Condvar,                     // jobs were pushed, or the last one is done
+    tops     : Mutex<Vec<Option<Node>>>,    // nodes of the entries scan_all was given
+}
+
+// entries waiting to be scanned, shared by all scanning threads. Taking the last one
+// pushed goes depth first, so only the directories on the way down stay open
+struct Stack<D> {
+    jobs   : Vec<Job<D>>,
+    active : usize,     // jobs being scanned, that can still push more
+    failed : bool,      // a thread panicked, the others stop so that the panic gets through
+}
+
+// an entry to scan, and where its node goes
+struct Job<D> {
+    dir       : Option<Arc<D>>,             // the directory it is in, None for scan roots
+    name      : OsString,
+    file_type : Option<FileType>,
+    prestat   : Option<io::Result<Stat>>,
+    parents   : Arc<Parents>,
+    dest      : Dest,
+}
+
+enum Dest {
+    Top( usize ),                   // one of the entries given to scan_all
+    Child( Arc<Slot>, usize ),      // an entry of a directory
+}
+
+// a directory waiting for its entries. The last one scanned puts it where it goes
+type Slot = Mutex<Pending>;
+
+struct Pending {
+    node     : Option<Node>,
+    dest     : Option<Dest>,
+    children : Vec<Option<Node>>,  // None for the ones not placed yet
+    left     : usize,
+}
+
+// what a directory passes down to its entries
+#[derive(Clone)]
+struct Parents {
+    ids      : Option<Arc<Ancestor>>,   // the directories above, None for scan roots
+    ignores  : Option<Ignores>,         // only with --respect-gitignore or --only-ignored
+    excludes : Ignores,                 // from .dutreeignore files
+    path     : Arc<Path>,               // of the scan root, as given
+    root     : usize,                   // index of the scan root, in the order they were given
+    root_dev : Option<u64>,             // st_dev of the scan root, only with --one-file-system
+    claimed  : bool,                    // the entry is a deferred link, already in visited
+}
+
+// paths are only built from the directories above when needed, to report errors and
+// progress or to match patterns, so that entries don't cost more the deeper they are
+impl Parents {
+    // path of an entry relative to the scan root, empty for the root itself
+    fn rel( &self, name : &OsStr ) -> PathBuf {
+        match self.ids {
+            Some( _ ) => self.names().chain( Some( name ) ).collect(),
+            None      => PathBuf::new(),
+        }
+    }
+
+    // path of the directory the entries are in relative to the scan root
+    fn dir_rel( &self ) -> PathBuf {
+        self.names().collect()
+    }
+
+    // path of an entry below the scan root as given, or of the root itself
+    fn path( &self, name : &OsStr ) -> PathBuf {
+        let mut path = self.path.to_path_buf();
+        path.extend( self.names().chain( self.ids.is_some().then_some( name ) ) );
+        path
+    }
+
+    // of the directories below the scan root, outermost first
+    fn names( &self ) -> impl Iterator<Item = &OsStr> {
+        let mut names = Vec::new();
+        let mut next = self.ids.as_deref();
+        while let Some( ancestor ) = next {
+            next = ancestor.up.as_deref();
+            if next.is_some() { names.push( ancestor.name.as_os_str() ) }  // the root is already in path
+        }
+        names.into_iter().rev()
+    }
+}
+
+// ( st_dev, st_ino ) and name of a directory, shared by everything below it
+struct Ancestor {
+    id   : (u64, u64),
+    name : OsString,
+    up   : Option<Arc<Ancestor>>,
+}
+
+impl Ancestor {
+    fn contains( ids : &Option<Arc<Ancestor>>, id : (u64, u64) ) -> bool {
+        let mut next = ids.as_deref();
+        while let Some( ancestor ) = next {
+            if ancestor.id == id { return true }
+            next = ancestor.up.as_deref();
+        }
+        false
+    }
+}
+
+// one by one, dropping a deep chain recursively would overflow the stack
+impl Drop for Ancestor {
+    fn drop( &mut self ) {
+        let mut up = self.up.take();
+        while let Some( ancestor ) = up {
+            up = match Arc::try_unwrap( ancestor ) {
+                Ok( mut ancestor ) => ancestor.up.take(),
+                Err( _ )           => None,
+            };
+        }
+    }
+}
+
+// a link to a directory found with -L, followed once the rest of the tree is scanned. The
+// directory it is in stays open until then
+struct Deferred<D> {
+    dir       : Arc<D>,
+    name      : OsString,
+    file_type : Option<FileType>,
+    id        : (u64, u64),
+    parents   : Parents,
+}
+
+// ( root, rel, ( st_dev, st_ino ) ) of a directory reached through its own path with -L,
+// see settle_claims
+type Claim = ( usize, PathBuf, (u64, u64) );
+
+// a directory read by scan_entry, its entries still to be scanned
+struct Listing<D> {
+    dir      : D,
+    entries  : Vec<DirEntry>,
+    prestats : Vec<Option<io::Result<Stat>>>,
+    parents  : Parents,
+}
+
+// Each entry is stat'ed once. Names and file types come from the parent directory
+// listing, and only symlinks that are followed need one more call to read the target
+pub fn scan( paths : &[PathBuf], cfg : &Config, state : &ScanState ) -> Vec<Node> {
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    if let Some( uring ) = backend::uring::Uring::new() {
+        return scan_with( &uring, paths, cfg, state );
+    }
+    #[cfg(target_os = "linux")]
+    return scan_with( &backend::linux::Linux::new(), paths, cfg, state );
+    #[cfg(not(target_os = "linux"))]
+    return scan_with( &backend::portable::Std, paths, cfg, state );
+}
+
+// roots are scanned together, one node each in the same order
+pub fn scan_with<B : Backend>( backend : &B, paths : &[PathBuf], cfg : &Config, state : &ScanState ) -> Vec<Node> {
+    let walk = Walk { backend, cfg, state, deferred: Mutex::new( Vec::new() ), claims: Mutex::new( Vec::new() ),
+                      stack: Mutex::new( Stack { jobs: Vec::new(), active: 0, failed: false } ), wake: Condvar::new(),
+                      tops: Mutex::new( Vec::new() ) };
+    let jobs = paths.iter().enumerate().map( |( root, path )| {
+        let root_dev = if cfg.xdev_flag { backend.stat( At::Root( path ), true ).ok().map( |s| s.dev ) }
+                       else { None };
+        let ignores = ( cfg.ignore_flag || cfg.only_ignore ).then( || Ignores::root( path ) );
+        let parents = Parents { ids: None, ignores, excludes: Ignores::dutree( path ), path: Arc::from( path.as_path() ),
+                                root, root_dev, claimed: false };
+        Job { dir: None, name: file_name_from_path( path ), file_type: None, prestat: None,
+              parents: Arc::new( parents ), dest: Dest::Top( root ) }
+    } ).collect();
+    // roots are never left out
+    let mut roots : Vec<Node> = scan_all( jobs, &walk ).into_iter().map( Option::unwrap ).collect();
+    settle_claims( &mut roots, &walk );
+    follow_deferred( &mut roots, &walk );
+    roots
+}
+
+// With -L, a directory reached through several of its own paths, with overlapping roots or
+// bind mounts, is counted under the first one in the order the roots are given and then in
+// path order, whichever thread got there first. The others are left as placeholders
+fn settle_claims<B : Backend>( roots : &mut [Node], walk : &Walk<B> ) {
+    let mut claims = mem::take( &mut *walk.claims.lock().unwrap() );
+    claims.sort();
+    let mut visited = walk.state.visited.lock().unwrap();
+    // what is below a placeholder comes right after it, and is gone with it
+    let mut dropped : Option<( usize, PathBuf )> = None;
+    for ( root, rel, id ) in claims {
+        if dropped.as_ref().is_some_and( |( r, path )| *r == root && rel.starts_with( path ) ) { continue }
+        if visited.insert( id ) { continue }
+        if let Some( node ) = find_node( roots, root, &rel ) {
+            let name = mem::take( &mut node.name );
+            *node = Node { ignored: node.ignored, excluded: node.excluded, uid: node.uid, gid: node.gid,
+                           ..Node::skipped_dir( name, "[counted elsewhere]", node.file_type, node.mode ) };
+        }
+        dropped = Some( ( root, rel ) );
+    }
+}
+
+// the node at rel below a root, None if it is not in the tree anymore
+fn find_node<'a>( roots : &'a mut [Node], root : usize, rel : &Path ) -> Option<&'a mut Node> {
+    let mut node = &mut roots[root];
+    for name in rel.iter() {
+        node = node.children.iter_mut().flatten().find( |c| c.name == name )?;
+    }
+    Some( node )
+}
+
+// With -L, every directory is counted once. The ones reached through their own path come
+// first, from all roots, then links in path order: the first link to a directory is
+// followed, the others are shown as plain links like loops are. Links found in followed
+// directories wait for the next round
+fn follow_deferred<B : Backend>( roots : &mut [Node], walk : &Walk<B> ) {
+    loop {
+        let mut deferred = mem::take( &mut *walk.deferred.lock().unwrap() );
+        if deferred.is_empty() { break }
+        deferred.sort_by_cached_key( |link| ( link.parents.root, link.parents.rel( &link.name ) ) );
+
+        // links below placeholders left by settle_claims are gone
+        let mut visited = walk.state.visited.lock().unwrap();
+        deferred.retain( |link| {
+            find_node( roots, link.parents.root, &link.parents.rel( &link.name ) ).is_some() &&
+                visited.insert( link.id )
+        } );
+        drop( visited );
+
+        let places : Vec<( usize, PathBuf )> = deferred.iter().map( |link| {
+            ( link.parents.root, link.parents.rel( &link.name ) )
+        } ).collect();
+        let jobs = deferred.into_iter().enumerate().map( |( i, link )| {
+            Job { dir: Some( link.dir ), name: link.name, file_type: link.file_type, prestat: None,
+                  parents: Arc::new( link.parents ), dest: Dest::Top( i ) }
+        } ).collect();
+
+        for ( ( root, rel ), node ) in places.into_iter().zip( scan_all( jobs, walk ) ) {
+            let slot = find_node( roots, root, &rel ).unwrap();
+            match node {
+                Some( node ) => *slot = node,
+                None         => slot.partial = true,
+            }
+        }
+        settle_claims( roots, walk );
+    }
+}
+
+// scans the given entries and everything below them, one node each in the same order.
+// None for the ones left out because the scan stopped. The tree is walked with a stack
+// of entries rather than recursion, so how deep it goes is only limited by memory
+fn scan_all<B : Backend>( jobs : Vec<Job<B::Dir>>, walk : &Walk<B> ) -> Vec<Option<Node>> {
+    *walk.tops.lock().unwrap() = jobs.iter().map( |_| None ).collect();
+    walk.stack.lock().unwrap().jobs.extend( jobs.into_iter().rev() );
+    rayon::scope( |s| {
+        for _ in 0 .. rayon::current_num_threads() {
+            s.spawn( |_| work( walk ) );
+        }
+    } );
+    mem::take( &mut *walk.tops.lock().unwrap() )
+}
+
+// one scanning thread. Takes entries from the stack until there are none left and no
+// other thread can push more
+fn work<B : Backend>( walk : &Walk<B> ) {
+    let _failed = Failed( walk );
+    let mut found : Option<Vec<Job<B::Dir>>> = None;     // pushed by the last job, once it is done
+    loop {
+        let job = {
+            let mut stack = walk.stack.lock().unwrap();
+            if let Some( found ) = found.take() {
+                stack.active -= 1;
+                if !found.is_empty() {
+                    stack.jobs.extend( found.into_iter().rev() );
+                    walk.wake.notify_all();
+                }
+            }
+            loop {
+                if let Some( job ) = stack.jobs.pop() {
+                    stack.active += 1;
+                    break job;
+                }
+                if stack.active == 0 || stack.failed {
+                    walk.wake.notify_all();
+                    return;
+                }
+                stack = walk.wake.wait( stack ).unwrap();
+            }
+        };
+
+        // once the scan is stopped, entries not scanned yet stay as placeholders with what the
+        // listing tells about them. Roots are always scanned, deferred links are left as they are
+        found = Some( if job.dir.is_some() && walk.state.stopped() {
+            let node = match job.dest {
+                Dest::Child( .. ) => Some( Node::unscanned( job.name, job.file_type, &job.parents ) ),
+                Dest::Top( _ )    => None,
+            };
+            place( node, job.dest, walk );
+            Vec::new()
+        } else {
+            scan_node( job, walk )
+        } );
+    }
+}
+
+// wakes the other threads up if this one panics, or they would wait for it forever
+struct Failed<'a, 'b, B : Backend>( &'a Walk<'b, B> );
+
+impl<B : Backend> Drop for Failed<'_, '_, B> {
+    fn drop( &mut self ) {
+        if std::thread::panicking() {
+            if let Ok( mut stack ) = self.0.stack.lock() {
+                stack.failed = true;
+            }
+            self.0.wake.notify_all();
+        }
+    }
+}
+
+// puts a node where it goes. When it is the last entry of its directory, the directory is
+// done too and goes where it goes, and so on up
+fn place<B : Backend>( mut node : Option<Node>, mut dest : Dest, walk : &Walk<B> ) {
+    loop {
+        let ( slot, i ) = match dest {
+            Dest::Top( i )         => {
+                walk.tops.lock().unwrap()[i] = node;
+                return;
+            },
+            Dest::Child( slot, i ) => ( slot, i ),
+        };
+        let mut pending = slot.lock().unwrap();
+        pending.children[i] = node;
+        pending.left -= 1;
+        if pending.left > 0 { return }
+
+        let children = mem::take( &mut pending.children );
+        let mut dir  = pending.node.take().unwrap();
+        // also when filters drop the placeholders of what wasn't scanned
+        dir.partial |= children.iter().flatten().any( |c| c.partial && c.children.is_none() );
+        dir.children = Some( children.into_iter().flatten().collect() );
+        dest = pending.dest.take().unwrap();
+        node = Some( dir );
+    }
+}
+
+// scans an entry, and returns the ones in it if it is a directory to read. Its node is
+// placed once they are all done
+fn scan_node<B : Backend>( job : Job<B::Dir>, walk : &Walk<B> ) -> Vec<Job<B::Dir>> {
+    let Job { dir, name, file_type, prestat, parents, dest } = job;
+    let at = match dir {
+        Some( ref dir ) => At::Entry( &**dir, &name ),
+        None            => At::Root( &parents.path ),
+    };
+    let ( node, listing ) = scan_entry( at, name.clone(), file_type, prestat, &parents, walk );
+
+    if let ( Some( id ), Some( dir ) ) = ( node.deferred, &dir ) {
+        walk.deferred.lock().unwrap().push( Deferred {
+            dir: dir.clone(), name, file_type: node.file_type, id,
+            parents: Parents { claimed: true, ..( *parents ).clone() },
+        } );
+    }
+
+    let listing = match listing {
+        Some( listing ) => listing,
+        None            => {
+            place( Some( node ), dest, walk );
+            return Vec::new();
+        },
+    };
+    let Listing { dir, entries, prestats, parents } = listing;
+    let ( dir, parents ) = ( Arc::new( dir ), Arc::new( parents ) );
+    let slot = Arc::new( Mutex::new( Pending { node: Some( node ), dest: Some( dest ),
+                                               children: entries.iter().map( |_| None ).collect(),
+                                               left: entries.len() } ) );
+    let mut jobs : Vec<Job<B::Dir>> = entries.into_iter().zip( prestats ).enumerate().map( |( i, ( entry, prestat ) )| {
+        Job { dir: Some( dir.clone() ), name: entry.name, file_type: entry.file_type, prestat, parents: parents.clone(), dest: Dest::Child( slot.clone(), i ) }
+    } ).collect();
+    // every job keeps the directory open. Directories go last: by the time the last one is
+    // taken the other entries are done, and the directory is closed before going further down
+    jobs.sort_by_key( |job| matches!( job.file_type, Some( FileType::Dir ) | None ) );
+    jobs
+}
+
+// the entry is reached through at, its path is only built to report errors and progress.
+// prestat is the result of the first stat, if the parent already did it. Directories
+// with entries to scan come with them, their node without children yet
+fn scan_entry<B : Backend>( at : At<B::Dir>, name : OsString, mut file_type : Option<FileType>,
+                            prestat : Option<io::Result<Stat>>, parents : &Parents,
+                            walk : &Walk<B> ) -> ( Node, Option<Listing<B::Dir>> ) {
+    let ( cfg, errors ) = ( walk.cfg, &walk.state.errors );
+    let path         = || parents.path( &name );
+    let is_root      = parents.ids.is_none();
+    let follow_links = cfg.follow_flag || ( cfg.follow_root && is_root );
+    let mut follow   = first_follow( follow_links, file_type );
+    let mut stat     = try_stat_with( walk.backend, at, path, &mut follow, prestat, errors );
+
+    // roots, and filesystems that don't give the type when listing a directory
+    if file_type.is_none() {
+        file_type = stat.and_then( |s| s.file_type() );
+        if follow_links && file_type == Some( FileType::Symlink ) {
+            follow = true;
+            stat   = try_stat_with( walk.backend, at, path, &mut follow, None, errors );
+        }
+    }
+
+    // links back to one of the directories above would make the scan never end
+    if follow && stat.is_some_and( |s| s.is_dir() && Ancestor::contains( &parents.ids, ( s.dev, s.ino ) ) ) {
+        errors.push( &path(), ErrorKind::SymlinkLoop );
+        follow = false;
+        stat   = try_stat( walk.backend, at, path, false, errors );
+    }
+    // other links to directories wait until the rest is scanned, they may lead to one
+    // that is also reached through its own path
+    let mut deferred = None;
+    if follow && cfg.follow_flag && !is_root && !parents.claimed && stat.is_some_and( |s| s.is_dir() ) &&
+       !is_left_out( cfg, is_ignored( parents, &name, true ), is_excluded( parents, &name, true ), true ) {
+        deferred = stat.map( |s| ( s.dev, s.ino ) );
+        follow   = false;
+        stat     = try_stat( walk.backend, at, path, false, errors );
+    }
+    let stat = match stat {
+        Some( stat ) => stat,
+        None         => {
+            let ( ignored, excluded ) = ( is_ignored( parents, &name, false ), is_excluded( parents, &name, false ) );
+            return ( Node { ignored, excluded, ..Node::unreadable( name, file_type ) }, None );
+        },
+    };
+    let mode    = stat.mode & 0o7777;
+    let is_dir  = stat.is_dir();
+    let ignored  = is_ignored( parents, &name, is_dir );
+    let excluded = is_excluded( parents, &name, is_dir );
+    if is_dir && parents.root_dev.is_some_and( |dev| stat.dev != dev ) {
+        return ( Node { ignored, excluded, uid: stat.uid, gid: stat.gid,
+                        ..Node::skipped_dir( name, "[mount point]", file_type, mode ) }, None );
+    }
+
+    let target = if follow { walk.backend.read_link( at ).ok() } else { None };
+    let ( apparent, usage ) = ( stat.size, stat.blocks * 512 );
+    // with -L the same file can also be reached through several symlinks
+    let link = if is_dir || ( stat.nlink < 2 && !cfg.follow_flag ) { None } else { Some( ( stat.dev, stat.ino ) ) };
+    walk.state.progress.add( if cfg.usage_flag { usage } else { apparent } );
+
+    // left out of the tree anyway, what's in them is not even read
+    let skipped = !is_root && is_left_out( cfg, ignored, excluded, is_dir );
+    // with -L, directories counted in an earlier round are not read again. The ones reached
+    // twice in this round are, and settle_claims keeps one
+    if is_dir && !skipped && cfg.follow_flag && !parents.claimed {
+        let id = ( stat.dev, stat.ino );
+        if walk.state.visited.lock().unwrap().contains( &id ) {
+            return ( Node { ignored, excluded, uid: stat.uid, gid: stat.gid,
+                            ..Node::skipped_dir( name, "[counted elsewhere]", file_type, mode ) }, None );
+        }
+        walk.claims.lock().unwrap().push( ( parents.root, parents.rel( &name ), id ) );
+    }
+
+    let mut cache   = false;
+    let mut listing = None;
+    let children = if is_dir && !skipped {
+        walk.state.progress.enter( path );
+        try_read_dir( walk.backend, at, path, follow, errors ).map( |( dir, dentries )| {
+            // files listed in the directory are read through it, so that paths longer than
+            // PATH_MAX are no problem. Missing ones are not even opened
+            let read = |file : &str, limit| {
+                let first = file.split( '/' ).next().unwrap();
+                if !dentries.iter().any( |entry| entry.name == first ) { return None }
+                walk.backend.read_file( At::Entry( &dir, OsStr::new( file ) ), limit ).ok()
+            };
+            let rel      = || parents.rel( &name );
+            let ignores  = parents.ignores.as_ref().map( |ignores| {
+                ignores.enter( rel, ignored, |file| read( file, u64::MAX ) )
+            } );
+            let excludes = parents.excludes.enter( rel, excluded, |file| read( file, u64::MAX ) );
+            let ids      = Some( Arc::new( Ancestor { id: ( stat.dev, stat.ino ), name: name.clone(), up: parents.ids.clone() } ) );
+            let parents  = Parents { ids, ignores, excludes, path: parents.path.clone(), claimed: false, ..*parents };
+
+            // skipped caches are left out anyway, no need to read them. Roots are never skipped.
+            // Tag files only have to start with the signature
+            cache = cfg.cache_flag && !is_root &&
+                    read( CACHEDIR_TAG, CACHEDIR_SIGNATURE.len() as u64 ).is_some_and( |start| start == CACHEDIR_SIGNATURE );
+            if cache && !cfg.cache_fold {
+                return Vec::new();
+            }
+
+            // entries filtered out by name or path are not even stat'ed. -f also leaves out
+            // links to directories, only those need a stat first
+            let dir_rel = OnceCell::new();
+            let entries : Vec<DirEntry> = dentries.into_iter().filter( |entry| {
+                let is_dir = cfg.no_dir_flg && match entry.file_type {
+                    Some( FileType::Dir )            => true,
+                    Some( FileType::Symlink ) | None => {
+                        walk.backend.stat( At::Entry( &dir, &entry.name ), true ).is_ok_and( |s| s.is_dir() )
+                    },
+                    _                                => false,
+                };
+                !is_dir && !crate::is_skipped( &entry.name, || dir_rel.get_or_init( || parents.dir_rel() ), cfg )
+            } ).collect();
+            if entries.is_empty() {
+                return Vec::new();
+            }
+
+            let requests : Vec<( &OsStr, bool )> = entries.iter().map( |entry| {
+                ( entry.name.as_os_str(), first_follow( cfg.follow_flag, entry.file_type ) )
+            } ).collect();
+            let prestats : Vec<Option<io::Result<Stat>>> = match walk.backend.stat_batch( &dir, &requests ) {
+                Some( stats ) => stats.into_iter().map( Some ).collect(),
+                None          => entries.iter().map( |_| None ).collect(),
+            };
+            listing = Some( Listing { dir, entries, prestats, parents } );
+            Vec::new()
+        } )
+    } else {
+        None
+    };
+
+    ( Node { name, apparent, usage, is_dir, link, children, tag: cache.then_some( "[cache]" ), target, file_type,
+             mode, partial: false, ignored, excluded, cache, deferred, mtime: stat.mtime, atime: stat.atime,
+             uid: stat.uid, gid: stat.gid }, listing )
+}
+
+fn is_left_out( cfg : &Config, ignored : bool, excluded : bool, is_dir : bool ) -> bool {
+    excluded || ( cfg.ignore_flag && ignored ) || ( cfg.no_dir_flg && is_dir )
+}
+
+// roots are matched against the ignore files above them when setting up the scan
+fn is_ignored( parents : &Parents, name : &OsStr, is_dir : bool ) -> bool {
+    match parents.ignores {
+        Some( ref ignores ) => ignores.is_ignored( parents.ids.is_some().then_some( name ), is_dir ),
+        None                => false,
+    }
+}
+
+fn is_excluded( parents : &Parents, name : &OsStr, is_dir : bool ) -> bool {
+    parents.excludes.is_ignored( parents.ids.is_some().then_some( name ), is_dir )
+}
+
+// see https://bford.info/cachedir/
+const CACHEDIR_TAG : &str = "CACHEDIR.TAG";
+const CACHEDIR_SIGNATURE : &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+fn try_is_symlink( path : &Path ) -> bool {
+    let metadata = path.symlink_metadata();
+    metadata.is_ok() && metadata.unwrap().file_type().is_symlink()
+}
+
+// scan roots can be given as . or .., which have no file name of their own
+fn file_name_from_path( path : &Path ) -> OsString {
+    let mut abspath = std::env::current_dir().unwrap();
+    abspath.push( path );
+
+    // don't resolve links
+    if !try_is_symlink( path ) {
+        abspath = abspath.canonicalize().unwrap_or( abspath );
+    }
+
+    abspath.file_name().unwrap_or( OsStr::new( "/" ) ).to_os_string()  // '/' has no filename
+}
+
+// whether the first stat of an entry follows it. Entries of unknown type are
+// stat'ed without following first, and again if they turn out to be links
+fn first_follow( follow_links : bool, file_type : Option<FileType> ) -> bool {
+    follow_links && file_type == Some( FileType::Symlink )
+}
+
+fn try_stat<B : Backend>( backend : &B, at : At<B::Dir>, path : impl Fn() -> PathBuf, mut follow : bool,
+                          errors : &Errors ) -> Option<Stat> {
+    try_stat_with( backend, at, path, &mut follow, None, errors )
+}
+
+// followed links report what they point to, orphan links stay links and follow is
+// cleared for them. path is only called to report an error
+fn try_stat_with<B : Backend>( backend : &B, at : At<B::Dir>, path : impl Fn() -> PathBuf, follow : &mut bool,
+                               prestat : Option<io::Result<Stat>>, errors : &Errors ) -> Option<Stat> {
+    let first = prestat.unwrap_or_else( || backend.stat( at, *follow ) );
+    let stat  = match first {
+        Err( _ ) if *follow => {
+            *follow = false;
+            backend.stat( at, false )
+        },
+        first               => first,
+    };
+    match stat {
+        Ok( stat ) => Some( stat ),
+        Err( err ) => {
+            errors.push_io( &path(), &err );
+            None
+        },
+    }
+}
+
+// entries that can't be read are reported against their directory
+fn try_read_dir<B : Backend>( backend : &B, at : At<B::Dir>, path : impl Fn() -> PathBuf, follow : bool,
+                              errors : &Errors ) -> Option<( B::Dir, Vec<DirEntry> )> {
+    let list = backend.open_dir( at, follow ).and_then( |dir| Ok( ( backend.read_dir( &dir )?, dir ) ) );
+    match list {
+        Ok( ( entries, dir ) ) => {
+            let entries = entries.into_iter().filter_map( |entry| {
+                entry.map_err( |err| errors.push_io( &path(), &err ) ).ok()
+            } ).collect();
+            Some( ( dir, entries ) )
+        },
+        Err( err ) => {
+            errors.push_io( &path(), &err );
+            None
+        },
+    }
+}
+
+// License
+//
+// This script is free software; you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This script is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this script; if not, write to the
+// Free Software Foundation, Inc., 59 Temple Place, Suite 330,